@@ -0,0 +1,158 @@
+// Lighting shared by the custom GLSL materials, imported into the fragment shaders.
+// Expects world space position and normal from custom_material.vert.
+// The simple path does lambert diffuse, blinn-phong specular and a rim term from the directional lights.
+// With PBR_LIGHTING defined it instead uses a GLSL port of the directional light and ambient
+// parts of Bevy's pbr_lighting.wgsl, so it matches StandardMaterial objects next to it.
+// Point lights live in the clustered storage buffers and are not handled here.
+
+struct DirectionalLight {
+    mat4  view_projection;
+    vec4  color;
+    vec3  direction_to_light;
+    uint  flags;
+    float shadow_depth_bias;
+    float shadow_normal_bias;
+};
+
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+    mat4 InverseViewProj;
+    mat4 View;
+    mat4 InverseView;
+    mat4 Projection;
+    mat4 InverseProjection;
+    vec3 WorldPosition;
+    vec4 Viewport;
+};
+
+// Must match the Lights struct in bevy_pbr mesh_view_types.wgsl (MAX_DIRECTIONAL_LIGHTS = 10)
+layout(set = 0, binding = 1) uniform Lights {
+    DirectionalLight DirectionalLights[10];
+    vec4  AmbientColor;
+    uvec4 ClusterDimensions;
+    vec4  ClusterFactors;
+    uint  NDirectionalLights;
+    int   SpotLightShadowmapOffset;
+};
+
+// Bevy's global shader values, bound once per view
+layout(set = 0, binding = 9) uniform Globals {
+    float Time;
+    float DeltaTime;
+    uint  FrameCount;
+};
+
+const float PI = 3.141592653589793;
+const float RIM_POWER = 4.0;
+
+#ifdef PBR_LIGHTING
+float D_GGX(float roughness, float NoH) {
+    float oneMinusNoHSquared = 1.0 - NoH * NoH;
+    float a = NoH * roughness;
+    float k = roughness / (oneMinusNoHSquared + a * a);
+    return k * k * (1.0 / PI);
+}
+
+float V_SmithGGXCorrelated(float roughness, float NoV, float NoL) {
+    float a2 = roughness * roughness;
+    float lambdaV = NoL * sqrt((NoV - a2 * NoV) * NoV + a2);
+    float lambdaL = NoV * sqrt((NoL - a2 * NoL) * NoL + a2);
+    return 0.5 / (lambdaV + lambdaL);
+}
+
+vec3 F_Schlick_vec(vec3 f0, float f90, float VoH) {
+    return f0 + (f90 - f0) * pow(1.0 - VoH, 5.0);
+}
+
+float F_Schlick(float f0, float f90, float VoH) {
+    return f0 + (f90 - f0) * pow(1.0 - VoH, 5.0);
+}
+
+vec3 fresnel(vec3 f0, float LoH) {
+    float f90 = clamp(dot(f0, vec3(50.0 * 0.33)), 0.0, 1.0);
+    return F_Schlick_vec(f0, f90, LoH);
+}
+
+float Fd_Burley(float roughness, float NoV, float NoL, float LoH) {
+    float f90 = 0.5 + 2.0 * roughness * LoH * LoH;
+    float lightScatter = F_Schlick(1.0, f90, NoL);
+    float viewScatter = F_Schlick(1.0, f90, NoV);
+    return lightScatter * viewScatter * (1.0 / PI);
+}
+
+vec3 EnvBRDFApprox(vec3 f0, float perceptual_roughness, float NoV) {
+    vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = perceptual_roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * NoV)) * r.x + r.y;
+    vec2 AB = vec2(-1.04, 1.04) * a004 + r.zw;
+    return f0 * AB.x + AB.y;
+}
+
+float perceptualRoughnessToRoughness(float perceptualRoughness) {
+    float clampedPerceptualRoughness = clamp(perceptualRoughness, 0.089, 1.0);
+    return clampedPerceptualRoughness * clampedPerceptualRoughness;
+}
+#endif
+
+// Light a surface point with the scene's directional lights and ambient light.
+vec3 custom_lighting(vec3 base_color, vec3 world_position, vec3 world_normal, float perceptual_roughness, float metallic, float rim) {
+    vec3 N = normalize(world_normal);
+    vec3 V = normalize(WorldPosition - world_position);
+    float NoV = max(dot(N, V), 0.0001);
+    vec3 rim_light = rim * pow(1.0 - NoV, RIM_POWER) * base_color;
+    vec3 light_accum = vec3(0.0);
+
+#ifdef PBR_LIGHTING
+    float roughness = perceptualRoughnessToRoughness(perceptual_roughness);
+    float reflectance = 0.5;
+    vec3 F0 = 0.16 * reflectance * reflectance * (1.0 - metallic) + base_color * metallic;
+    vec3 diffuse_color = base_color * (1.0 - metallic);
+
+    for (uint i = 0u; i < NDirectionalLights; i++) {
+        vec3 L = DirectionalLights[i].direction_to_light;
+        vec3 H = normalize(L + V);
+        float NoL = clamp(dot(N, L), 0.0, 1.0);
+        float NoH = clamp(dot(N, H), 0.0, 1.0);
+        float LoH = clamp(dot(L, H), 0.0, 1.0);
+
+        vec3 diffuse = diffuse_color * Fd_Burley(roughness, NoV, NoL, LoH);
+        vec3 specular = D_GGX(roughness, NoH) * V_SmithGGXCorrelated(roughness, NoV, NoL) * fresnel(F0, LoH);
+        light_accum += (diffuse + specular) * DirectionalLights[i].color.rgb * NoL;
+        light_accum += rim_light * DirectionalLights[i].color.rgb * (1.0 / PI);
+    }
+
+    vec3 diffuse_ambient = EnvBRDFApprox(diffuse_color, 1.0, NoV);
+    vec3 specular_ambient = EnvBRDFApprox(F0, perceptual_roughness, NoV);
+    return light_accum + (diffuse_ambient + specular_ambient) * AmbientColor.rgb;
+#else
+    // Roughness to blinn-phong exponent, energy normalized
+    float roughness = max(perceptual_roughness * perceptual_roughness, 0.01);
+    float shininess = 2.0 / (roughness * roughness) - 2.0;
+    float specular_norm = (shininess + 8.0) / (8.0 * PI);
+
+    for (uint i = 0u; i < NDirectionalLights; i++) {
+        vec3 L = DirectionalLights[i].direction_to_light;
+        vec3 H = normalize(L + V);
+        float NoL = clamp(dot(N, L), 0.0, 1.0);
+        float NoH = clamp(dot(N, H), 0.0, 1.0);
+
+        vec3 diffuse = base_color * (1.0 - metallic) * (1.0 / PI);
+        vec3 specular = mix(vec3(0.04), base_color, metallic) * specular_norm * pow(NoH, shininess);
+        light_accum += (diffuse + specular) * DirectionalLights[i].color.rgb * NoL;
+        light_accum += rim_light * DirectionalLights[i].color.rgb * (1.0 / PI);
+    }
+
+    return light_accum + base_color * AmbientColor.rgb;
+#endif
+}
+
+// Same reinhard luminance tone mapping as Bevy's pbr shader when not rendering to HDR
+vec4 custom_tone_mapping(vec4 color) {
+#ifdef TONEMAP_IN_SHADER
+    float luminance_old = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float luminance_new = luminance_old / (1.0 + luminance_old);
+    color.rgb = color.rgb * (luminance_new / max(luminance_old, 0.0001));
+#endif
+    return color;
+}
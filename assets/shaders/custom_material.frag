@@ -1,17 +1,55 @@
 #version 450
+// Fragment shader for every variant of MyCustomMaterial.
+// Features are switched on by shader defines from the material key:
+// SCROLLING_TEXTURE, MARBLE_NOISE, ALPHA_BLEND, LIGHTING and PBR_LIGHTING.
 layout(location = 0) in vec2 v_Uv;
+layout(location = 1) in vec3 v_WorldPosition;
+layout(location = 2) in vec3 v_WorldNormal;
 
 layout(location = 0) out vec4 o_Target;
 
+#import "shaders/custom_lighting.frag"
+
 layout(set = 1, binding = 0) uniform MyCustomMaterial {
     vec4  Color;
-    float Time;
+    float Roughness;
+    float Metallic;
+    float Rim;
+    float Distortion;
+    vec2  ScrollSpeed;
 };
 
-layout(set = 1, binding = 1) uniform texture2D MyCustomMaterial_texture;
-layout(set = 1, binding = 2) uniform sampler MyCustomMaterial_sampler;
+layout(set = 1, binding = 1) uniform texture2D base_color_texture;
+layout(set = 1, binding = 2) uniform sampler   base_color_sampler;
+
+layout(set = 1, binding = 3) uniform texture2D noise_texture;
+layout(set = 1, binding = 4) uniform sampler   noise_sampler;
 
 
 void main() {
-    o_Target = Color * texture(sampler2D(MyCustomMaterial_texture,MyCustomMaterial_sampler), v_Uv + vec2(0.0, Time * 0.1));
+    vec2 uv = v_Uv;
+#ifdef SCROLLING_TEXTURE
+    uv += ScrollSpeed * Time;
+#endif
+
+#ifdef MARBLE_NOISE
+    float anim_t = sin(Time * 0.4);
+    float noise_x = texture(sampler2D(noise_texture, noise_sampler), v_Uv + vec2(0.0, anim_t)).r;
+    float noise_y = texture(sampler2D(noise_texture, noise_sampler), v_Uv + vec2(0.0, -anim_t)).r;
+    vec4 base_color = Color * texture(sampler2D(base_color_texture, base_color_sampler), uv + vec2(noise_x, noise_y) * Distortion);
+    base_color += vec4(noise_x, noise_y, 0., 1.) * .2;
+#else
+    vec4 base_color = Color * texture(sampler2D(base_color_texture, base_color_sampler), uv);
+#endif
+
+#ifndef ALPHA_BLEND
+    base_color.a = 1.0;
+#endif
+
+#ifdef LIGHTING
+    vec3 lit_color = custom_lighting(base_color.rgb, v_WorldPosition, v_WorldNormal, Roughness, Metallic, Rim);
+    o_Target = custom_tone_mapping(vec4(lit_color, base_color.a));
+#else
+    o_Target = base_color;
+#endif
 }
@@ -5,15 +5,18 @@ layout(location = 1) in vec3 Vertex_Normal;
 layout(location = 2) in vec2 Vertex_Uv;
 
 layout(location = 0) out vec2 v_Uv;
+layout(location = 1) out vec3 v_WorldPosition;
+layout(location = 2) out vec3 v_WorldNormal;
 
 layout(set = 0, binding = 0) uniform CameraViewProj {
     mat4 ViewProj;
+    mat4 InverseViewProj;
     mat4 View;
     mat4 InverseView;
     mat4 Projection;
+    mat4 InverseProjection;
     vec3 WorldPosition;
-    float width;
-    float height;
+    vec4 Viewport;
 };
 
 layout(set = 2, binding = 0) uniform Mesh {
@@ -23,6 +26,9 @@ layout(set = 2, binding = 0) uniform Mesh {
 };
 
 void main() {
+    vec4 world_position = Model * vec4(Vertex_Position, 1.0);
     v_Uv = Vertex_Uv;
-    gl_Position = ViewProj * Model * vec4(Vertex_Position, 1.0);
+    v_WorldPosition = world_position.xyz;
+    v_WorldNormal = mat3(InverseTransposeModel) * Vertex_Normal;
+    gl_Position = ViewProj * world_position;
 }
@@ -7,11 +7,9 @@
 
 // Project module declaration (same as file names)
 mod player;
-mod camera;
 
 // Includes from project modules
 use player::PlayerPlugin;
-use camera::CameraPlugin;
 
 // External includes
 
@@ -21,20 +19,21 @@ use std::{
 
 // Bevy includes
 
-use bevy::{
-    pbr::{MaterialPipeline, MaterialPipelineKey},
-    prelude::*,
-    reflect::TypeUuid,
-    render::{
-        mesh::MeshVertexBufferLayout,
-        render_resource::{
-            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
-        },
-    }
-};
+use bevy::prelude::*;
 use bevy_prototype_debug_lines::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+// Shared playground includes
+
+use bevy_playground::{
+    camera_path::{CameraPathPlayer, CameraPathPlugin, camera_path_update},
+    camera_rig::{CameraMode, CameraRig, CameraRigPlugin},
+    camera_shake::{CameraShake, CameraShakePlugin},
+    custom_material::MyCustomMaterial,
+    material_asset::MaterialAssetPlugin,
+    mouse_look::MouseLookPlugin,
+};
+
 // Component types
 
 #[derive(Component)]
@@ -55,15 +54,6 @@ impl Default for Speed
     }
 }
 
-#[derive(Component)]
-struct CameraRotation(Vec2);
-impl Default for CameraRotation
-{
-    fn default() -> Self {
-        Self(Vec2::splat(0.))
-    }
-}
-
 // Update order labels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[derive(SystemLabel)]
@@ -78,7 +68,13 @@ const FLOOR_SIZE: Vec3 = Vec3::new(80., 8., 80.);
 const FLOOR_POSITION: Vec3 = Vec3::new(0., -FLOOR_SIZE.y * 0.5, 0.);
 const GAMEPAD_DEADZONE: f32 = 0.1;
 const GAMEPAD_AXIS_L_SENSITIVITY: f32 = 1.5;
-const GAMEPAD_AXIS_R_SENSITIVITY: f32 = 5.5;
+// Fixed camera shots around the playground
+const CAMERA_SHOTS: [Vec3; 4] = [
+    Vec3::new(20., 8., 20.),
+    Vec3::new(-20., 8., 20.),
+    Vec3::new(-20., 8., -20.),
+    Vec3::new(20., 8., -20.),
+];
 
 // App entry point
 
@@ -91,8 +87,16 @@ fn main() {
                 ..default()
             }))
         .add_plugin(MaterialPlugin::<MyCustomMaterial>::default())
+        .add_plugin(MaterialAssetPlugin)
         .add_plugin(PlayerPlugin)
-        .add_plugin(CameraPlugin)
+        .add_plugin(CameraRigPlugin::default()
+            .after(SystemOrder::PlayerMovement)
+            .label(SystemOrder::CameraMovement))
+        .add_plugin(MouseLookPlugin)
+        .add_plugin(CameraPathPlugin)
+        .add_plugin(CameraShakePlugin::default()
+            .after(SystemOrder::CameraMovement)
+            .after(camera_path_update))
         .add_plugin(DebugLinesPlugin::with_depth_test(true))
         .add_plugin(WorldInspectorPlugin)
         .add_startup_system(setup)
@@ -107,6 +111,18 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
+    // Make a camera, orbiting the marble until Tab switches modes
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0., 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    })
+    // Custom components
+    .insert(CameraRig::new(vec![CameraMode::Orbit, CameraMode::Follow, CameraMode::TopDown, CameraMode::Fixed, CameraMode::FreeFly])
+        .with_fixed_shots(CAMERA_SHOTS.to_vec()))
+    .insert(CameraPathPlayer::new(asset_server.load("paths/marble.campath.ron")))
+    .insert(CameraShake::default())
+    .insert(Camera);
+
     // Light the sphere
     commands.spawn(PointLightBundle {
         point_light: PointLight {
@@ -156,52 +172,3 @@ fn setup(
     debug!("x: {}, state: {:?}", 0.1, "test");
     trace!("entity transform: {:?}", Transform::from_xyz(-2.0, 2.5, 5.0));*/
 }
-
-
-
-// Shader buffer bindings
-// https://docs.rs/bevy/0.8.0/bevy/render/render_resource/trait.AsBindGroup.html
-#[derive(AsBindGroup, Clone, TypeUuid)]
-#[uuid = "69196246-07cd-4581-9885-167958593672"]
-pub struct MyCustomMaterial {
-    #[uniform(0)]
-    color: Color,
-    #[uniform(0)]
-    time: f32,
-    #[texture(1)]
-    #[sampler(2)]
-    color_texture: Option<Handle<Image>>,
-    #[texture(3)]
-    #[sampler(4)]
-    noise_texture: Option<Handle<Image>>,
-    alpha_mode: AlphaMode,
-}
-
-// Implement the material trait for our custom material struct in order to make it compliant with shader pipeline.
-// Override the behaviours for which we don't want the default behaviours.
- impl Material for MyCustomMaterial {
-    fn vertex_shader() -> ShaderRef {
-        "shaders/custom_material.vert".into()
-    }
-
-    fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material_marble.frag".into()
-    }
-
-    fn alpha_mode(&self) -> AlphaMode {
-        self.alpha_mode
-    }
-
-    // Specify shader program entrypoint overrides (not needed for WGSL)
-    fn specialize(
-        _pipeline: &MaterialPipeline<Self>,
-        descriptor: &mut RenderPipelineDescriptor,
-        _layout: &MeshVertexBufferLayout,
-        _key: MaterialPipelineKey<Self>,
-    ) -> Result<(), SpecializedMeshPipelineError> 
-    {
-        descriptor.vertex.entry_point = "main".into();
-        descriptor.fragment.as_mut().unwrap().entry_point = "main".into();
-        Ok(())
-    }
- }
\ No newline at end of file
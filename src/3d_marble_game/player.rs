@@ -1,6 +1,7 @@
 use bevy::{prelude::*};
 use bevy_prototype_debug_lines::*;
-use crate::{Player, Camera, CameraRotation, Speed, MyCustomMaterial, SystemOrder, 
+use bevy_playground::camera_rig::{CameraMode, CameraRig, CameraTarget};
+use crate::{Player, Camera, Speed, MyCustomMaterial, SystemOrder, 
     MARBLE_RADIUS, GAMEPAD_DEADZONE, GAMEPAD_AXIS_L_SENSITIVITY};
 
 pub struct PlayerPlugin;
@@ -10,35 +11,28 @@ impl Plugin for PlayerPlugin{
         app.add_startup_stage(
             "setup_player",
             SystemStage::single(player_spawn))
-        .add_system(player_movement.label(SystemOrder::PlayerMovement))
-        .add_system(player_animation);
+        .add_system(player_movement.label(SystemOrder::PlayerMovement));
     }
 }
 
 fn player_spawn(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<MyCustomMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
     // Make a player sphere
     commands.spawn((
-        MaterialMeshBundle {
+        MaterialMeshBundle::<MyCustomMaterial> {
             mesh: meshes.add(Mesh::from(shape::UVSphere { radius: MARBLE_RADIUS, sectors: 20, stacks: 20 })),
             transform: Transform::from_xyz(0.0, MARBLE_RADIUS, 0.0),
-            material: materials.add(MyCustomMaterial {
-                color: Color::BLUE,
-                time: 0.0,
-                color_texture: Some(asset_server.load("ball.png")),
-                noise_texture: Some(asset_server.load("manifold_noise.png")),
-                alpha_mode: AlphaMode::Blend,
-            }),
+            material: asset_server.load("materials/marble.material.ron"),
             ..default()
         },
         Name::new("Player")
     ))
     // Custom components
     .insert(Speed::default())
+    .insert(CameraTarget::default())
     .insert(Player);
 }
 
@@ -49,7 +43,7 @@ fn player_movement(
     gamepad_axes: Res<Axis<GamepadAxis>>,
     mut lines: ResMut<DebugLines>,
     mut player_query: Query<(&mut Speed, &mut Transform), (With<Player>, Without<Camera>)>,
-    camera_query: Query<&CameraRotation, (With<Camera>, Without<Player>)>
+    camera_query: Query<&CameraRig, (With<Camera>, Without<Player>)>
 ){  
     if let Ok((mut speed, mut transform)) = player_query.get_single_mut() {
         let dt = time.delta_seconds();
@@ -70,9 +64,12 @@ fn player_movement(
             }
         }
 
-        // Transform input to world space
-        if let Ok(angle) = camera_query.get_single() {
-            move_input = Quat::from_rotation_y(angle.0.y) * move_input;
+        // Transform input to world space, a free fly camera has the movement keys to itself
+        if let Ok(rig) = camera_query.get_single() {
+            if rig.mode == CameraMode::FreeFly {
+                move_input = Vec3::ZERO;
+            }
+            move_input = Quat::from_rotation_y(rig.yaw) * move_input;
         }
 
         // Accelerate
@@ -99,16 +96,3 @@ fn player_movement(
     }
     
 }
-
-fn player_animation(
-    time: Res<Time>,
-    mut materials: ResMut<Assets<MyCustomMaterial>>,
-    mut query: Query<&Handle<MyCustomMaterial>, With<Player>>
-){
-    for mat_handle in query.iter_mut() {
-        let time_sine = time.elapsed_seconds() as f32;
-        if let Some(mat) = materials.get_mut(mat_handle) {
-            mat.time = time_sine;
-        }
-    }
-}
\ No newline at end of file
@@ -3,18 +3,41 @@
 // 1 First get a static mesh working
 // 2 Get skinning and animation working
 // 3 Animation states
-// 4 Animation states with blending (available in Bevy 0.10...)
+// 4 Animation states with blending (blending available in Bevy 0.10..., until then we sample and blend clips ourselves)
 
 
 // Project module declaration (same as file names)
 mod player;
-mod camera;
 mod animation;
+mod animation_graph;
+mod animation_state;
+mod pose;
+mod character;
+mod root_motion;
+mod stride;
+mod foot_ik;
+mod look_at;
+mod skeleton_debug;
+mod controller;
+mod steering;
+mod npc;
+mod behaviour_tree;
+mod navigation;
 
 // Includes from project modules
 use player::PlayerPlugin;
-use camera::CameraPlugin;
 use animation::AnimationPlugin;
+use character::CharacterPlugin;
+use root_motion::RootMotionPlugin;
+use stride::StridePlugin;
+use foot_ik::FootIkPlugin;
+use look_at::LookAtPlugin;
+use skeleton_debug::SkeletonDebugPlugin;
+use controller::{CharacterControllerPlugin, StaticCollider};
+use steering::SteeringPlugin;
+use npc::NpcPlugin;
+use behaviour_tree::BehaviourTreePlugin;
+use navigation::NavigationPlugin;
 
 // External includes
 
@@ -25,20 +48,21 @@ use std::{
 // Bevy includes
 
 use bevy::{
-    pbr::{MaterialPipeline, MaterialPipelineKey},
     prelude::*,
-    reflect::{TypeUuid,        // For material id
-        Reflect, TypeRegistry},// For reflecting data to egui
-    render::{
-        mesh::MeshVertexBufferLayout,
-        render_resource::{
-            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
-        },
-    }
+    reflect::{Reflect, TypeRegistry},// For reflecting data to egui
 };
 use bevy_prototype_debug_lines::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+// Shared playground includes
+
+use bevy_playground::{
+    camera_rig::{CameraMode, CameraRig, CameraRigPlugin},
+    camera_shake::{CameraShake, CameraShakePlugin},
+    custom_material::MyCustomMaterial,
+    mouse_look::{MouseLookPlugin, MouseLookSettings},
+};
+
 // Component types
 
 #[derive(Component)]
@@ -60,15 +84,6 @@ impl Default for Speed
     }
 }
 
-#[derive(Component)]
-struct CameraRotation(Vec2);
-impl Default for CameraRotation
-{
-    fn default() -> Self {
-        Self(Vec2::splat(0.))
-    }
-}
-
 
 // Update order labels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -86,7 +101,13 @@ const FLOOR_SIZE: Vec3 = Vec3::new(80., 8., 80.);
 const FLOOR_POSITION: Vec3 = Vec3::new(0., -FLOOR_SIZE.y * 0.5, 0.);
 const GAMEPAD_DEADZONE: f32 = 0.1;
 const GAMEPAD_AXIS_L_SENSITIVITY: f32 = 1.5;
-const GAMEPAD_AXIS_R_SENSITIVITY: f32 = 5.5;
+// Fixed camera shots around the playground
+const CAMERA_SHOTS: [Vec3; 4] = [
+    Vec3::new(20., 8., 20.),
+    Vec3::new(-20., 8., 20.),
+    Vec3::new(-20., 8., -20.),
+    Vec3::new(20., 8., -20.),
+];
 
 // App entry point
 
@@ -100,8 +121,29 @@ fn main() {
             }))
         .add_plugin(MaterialPlugin::<MyCustomMaterial>::default())
         .add_plugin(PlayerPlugin)
-        .add_plugin(CameraPlugin)
+        .add_plugin(CameraRigPlugin::default()
+            .after(SystemOrder::PlayerMovement)
+            .label(SystemOrder::CameraMovement))
+        .add_plugin(MouseLookPlugin)
+        .add_plugin(CameraShakePlugin::default()
+            .after(SystemOrder::CameraMovement))
+        // Left click is click-to-move here, grab the mouse with the right button
+        .insert_resource(MouseLookSettings {
+            grab_button: MouseButton::Right,
+            ..default()
+        })
         .add_plugin(AnimationPlugin)
+        .add_plugin(CharacterPlugin)
+        .add_plugin(RootMotionPlugin)
+        .add_plugin(StridePlugin)
+        .add_plugin(FootIkPlugin)
+        .add_plugin(LookAtPlugin)
+        .add_plugin(SkeletonDebugPlugin)
+        .add_plugin(CharacterControllerPlugin)
+        .add_plugin(SteeringPlugin)
+        .add_plugin(NpcPlugin)
+        .add_plugin(BehaviourTreePlugin)
+        .add_plugin(NavigationPlugin)
         .add_plugin(DebugLinesPlugin::with_depth_test(true))
         .add_plugin(WorldInspectorPlugin)
         .add_startup_system(setup)
@@ -119,6 +161,17 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
+    // Make a camera, following the player until Tab switches modes
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0., 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    })
+    // Custom components
+    .insert(CameraRig::new(vec![CameraMode::Follow, CameraMode::Orbit, CameraMode::TopDown, CameraMode::Fixed, CameraMode::FreeFly])
+        .with_fixed_shots(CAMERA_SHOTS.to_vec()))
+    .insert(CameraShake::default())
+    .insert(Camera);
+
     // Light the sphere
     commands.spawn(PointLightBundle {
         point_light: PointLight {
@@ -159,7 +212,32 @@ fn setup(
         },
         Name::new("Floor")
     ))
-    .insert(FloorTile);
+    .insert(FloorTile)
+    .insert(StaticCollider::Box);
+
+    // Some things to walk on and into: ramps either side of the max slope, steps low enough to step up, a wall
+    let obstacles = [
+        ("Ramp",       Vec3::new(4., 0.4, 6.),   Transform::from_xyz(6., 0.85, -6.).with_rotation(Quat::from_rotation_x(20f32.to_radians()))),
+        ("Steep Ramp", Vec3::new(4., 0.4, 3.),   Transform::from_xyz(-6., 1.1, -6.).with_rotation(Quat::from_rotation_x(55f32.to_radians()))),
+        ("Step 1",     Vec3::new(3., 0.15, 3.),  Transform::from_xyz(0., 0.075, -8.)),
+        ("Step 2",     Vec3::new(3., 0.3, 2.),   Transform::from_xyz(0., 0.15, -8.5)),
+        ("Step 3",     Vec3::new(3., 0.45, 1.),  Transform::from_xyz(0., 0.225, -9.)),
+        ("Wall",       Vec3::new(6., 2., 0.5),   Transform::from_xyz(0., 1., 6.)),
+    ];
+    let obstacle_material = materials.add(Color::BEIGE.into());
+    for (name, size, transform) in obstacles {
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::new(size.x, size.y, size.z))),
+                material: obstacle_material.clone(),
+                transform,
+                ..default()
+            },
+            Name::new(name)
+        ))
+        .insert(FloorTile)
+        .insert(StaticCollider::Box);
+    }
 
     /* Log examples
     error!("Unknown condition!");
@@ -168,52 +246,3 @@ fn setup(
     debug!("x: {}, state: {:?}", 0.1, "test");
     trace!("entity transform: {:?}", Transform::from_xyz(-2.0, 2.5, 5.0));*/
 }
-
-
-
-// Shader buffer bindings
-// https://docs.rs/bevy/0.8.0/bevy/render/render_resource/trait.AsBindGroup.html
-#[derive(AsBindGroup, Clone, TypeUuid)]
-#[uuid = "69196246-07cd-4581-9885-167958593672"]
-pub struct MyCustomMaterial {
-    #[uniform(0)]
-    color: Color,
-    #[uniform(0)]
-    time: f32,
-    #[texture(1)]
-    #[sampler(2)]
-    color_texture: Option<Handle<Image>>,
-    #[texture(3)]
-    #[sampler(4)]
-    noise_texture: Option<Handle<Image>>,
-    alpha_mode: AlphaMode,
-}
-
-// Implement the material trait for our custom material struct in order to make it compliant with shader pipeline.
-// Override the behaviours for which we don't want the default behaviours.
- impl Material for MyCustomMaterial {
-    fn vertex_shader() -> ShaderRef {
-        "shaders/custom_material.vert".into()
-    }
-
-    fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material_marble.frag".into()
-    }
-
-    fn alpha_mode(&self) -> AlphaMode {
-        self.alpha_mode
-    }
-
-    // Specify shader program entrypoint overrides (not needed for WGSL)
-    fn specialize(
-        _pipeline: &MaterialPipeline<Self>,
-        descriptor: &mut RenderPipelineDescriptor,
-        _layout: &MeshVertexBufferLayout,
-        _key: MaterialPipelineKey<Self>,
-    ) -> Result<(), SpecializedMeshPipelineError> 
-    {
-        descriptor.vertex.entry_point = "main".into();
-        descriptor.fragment.as_mut().unwrap().entry_point = "main".into();
-        Ok(())
-    }
- }
\ No newline at end of file
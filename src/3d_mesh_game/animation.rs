@@ -1,18 +1,39 @@
-use bevy::{prelude::*};
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
+use crate::{
+    animation_graph::{AnimationGraph, AnimationGraphLoader},
+    animation_state::{animation_state_machine_update, AnimationEvent},
+};
 
 // Various animation help
 
 pub struct AnimationPlugin;
 
-// Component containing a link to an animation-player entity. Used for owning
-// top level entity to be able to reference a child animation player entity to control
-// the animations on a per-entity basis.
-#[derive(Component)]
-pub struct AnimationLink(pub Entity);
+// Component containing links to the animation-player entities below it. Used for owning
+// top level entity to be able to reference its child animation player entities to control
+// the animations on a per-entity basis. A character made of several skinned parts, or with
+// animated props attached, has one player per part, keyed by the name of that sub-hierarchy.
+#[derive(Component, Default)]
+pub struct AnimationLink {
+    pub players: HashMap<String, Entity>,
+}
+
+impl AnimationLink {
+    pub fn get(&self, part: &str) -> Option<Entity> {
+        self.players.get(part).copied()
+    }
+}
 
 impl Plugin for AnimationPlugin{
     fn build(&self, app: &mut App) {
-        app.add_system(animation_link_setup);
+        app.add_asset::<AnimationGraph>()
+        .init_asset_loader::<AnimationGraphLoader>()
+        .add_event::<AnimationEvent>()
+        .add_system(animation_link_cleanup)
+        .add_system(animation_link_setup.after(animation_link_cleanup))
+        // Our own clip sampling and blending, after the game logic but before transforms are propagated
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            animation_state_machine_update.before(TransformSystem::TransformPropagate));
     }
 }
 
@@ -23,19 +44,60 @@ fn find_top_parent (mut curr_entity: Entity, parent_query: &Query<&Parent>) -> E
     curr_entity
 }
 
-// When a new animation player is added, link to it in its topmost parent entity
+// Key for a player in AnimationLink, the name of the sub-hierarchy it animates
+fn player_key(anim_entity: Entity, names_query: &Query<&Name>) -> String {
+    match names_query.get(anim_entity) {
+        Ok(name) => name.to_string(),
+        Err(_) => format!("{:?}", anim_entity),
+    }
+}
+
+// When a new animation player is added, link to it in its topmost parent entity.
+// This also relinks the players of a reloaded scene, their old links are removed by animation_link_cleanup.
 fn animation_link_setup(
     added_anim_query: Query<Entity, Added<AnimationPlayer>>, // Newly added anim players
     parent_query: Query<&Parent>,                            // All parent entities
-    existing_anim_links_query: Query<&AnimationLink>,  // All existing anim links
+    names_query: Query<&Name>,
+    mut existing_anim_links_query: Query<&mut AnimationLink>,  // All existing anim links
     mut commands: Commands,
 ){  
+    // Gather per top entity first, a scene usually adds all its players in the same frame
+    let mut new_links: HashMap<Entity, AnimationLink> = HashMap::default();
     for anim_entity in added_anim_query.iter() {
         let top_parent = find_top_parent(anim_entity, &parent_query);
-        if existing_anim_links_query.get(top_parent).is_ok() {
-            warn!("Linking multiple animation players to one top entity currently not supported!");
+        let link = new_links.entry(top_parent).or_default();
+        let mut key = player_key(anim_entity, &names_query);
+        if link.players.contains_key(&key) {
+            warn!("Multiple animation players named {} below {:?}, linking the extra one as {} {:?}", key, top_parent, key, anim_entity);
+            key = format!("{} {:?}", key, anim_entity);
+        }
+        link.players.insert(key, anim_entity);
+    }
+
+    for (top_parent, new_link) in new_links {
+        if let Ok(mut link) = existing_anim_links_query.get_mut(top_parent) {
+            link.players.extend(new_link.players);
         } else {
-            commands.entity(top_parent).insert(AnimationLink(anim_entity));
+            commands.entity(top_parent).insert(new_link);
+        }
+    }
+}
+
+// Forget players that were despawned (or moved to another top entity), like when a scene is reloaded
+fn animation_link_cleanup(
+    mut links_query: Query<(Entity, &mut AnimationLink)>,
+    players_query: Query<(), With<AnimationPlayer>>,
+    parent_query: Query<&Parent>,
+){
+    // The link itself stays, so players spawned in the same frame can be added to it
+    for (top_parent, mut link) in links_query.iter_mut() {
+        let stale = link.players.values().any(|player| {
+            players_query.get(*player).is_err() || find_top_parent(*player, &parent_query) != top_parent
+        });
+        if stale {
+            link.players.retain(|_, player| {
+                players_query.get(*player).is_ok() && find_top_parent(*player, &parent_query) == top_parent
+            });
         }
     }
 }
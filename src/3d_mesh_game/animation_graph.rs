@@ -0,0 +1,185 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+// Animation graph asset, loaded from .animgraph.ron files.
+// Declares the states (which named clip to play and how), the transitions between them
+// with conditions on named parameters, and the entry state.
+// Clips are referenced by name, the character provides the actual clips, so the same graph
+// can drive any character that has clips with those names. See animation_state.rs for the runtime.
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Loop,
+    Once, // Stop at the last frame, see Condition::Finished
+}
+
+// Clip playback rate, optionally driven by a parameter: clamp(base + scale * param, min, max)
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PlaybackRate {
+    pub base: f32,
+    pub param: Option<String>,
+    pub scale: f32,
+    pub min: f32,
+    pub max: f32,
+}
+impl Default for PlaybackRate
+{
+    fn default() -> Self {
+        Self {
+            base: 1.,
+            param: None,
+            scale: 0.,
+            min: 0.,
+            max: f32::MAX,
+        }
+    }
+}
+
+impl PlaybackRate {
+    pub fn evaluate(&self, params: &AnimationParams) -> f32 {
+        let param = self.param.as_ref().map_or(0., |name| params.get(name));
+        (self.base + self.scale * param).clamp(self.min, self.max)
+    }
+}
+
+fn default_loop_mode() -> LoopMode {
+    LoopMode::Loop
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GraphState {
+    pub clip: String,
+    #[serde(default = "default_loop_mode")]
+    pub loop_mode: LoopMode,
+    #[serde(default)]
+    pub rate: PlaybackRate,
+    // States in the same sync group play at the same normalized time, like walk and run
+    #[serde(default)]
+    pub sync_group: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    IsTrue(String),
+    IsFalse(String),
+    Finished, // The current state's clip has played to the end, only Once clips do
+}
+
+fn default_blend() -> f32 {
+    0.25
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Transition {
+    pub from: String, // State name, or "*" for any state
+    pub to: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>, // All must hold
+    #[serde(default = "default_blend")]
+    pub blend: f32,                 // Cross-fade time in seconds
+}
+
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "2f0bd3e4-7a56-4c1e-9d6b-5c8e0a4b7f21"]
+pub struct AnimationGraph {
+    pub entry: String,
+    pub states: HashMap<String, GraphState>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+impl AnimationGraph {
+    // Check that all state names used by the graph exist
+    fn validate(&self) -> Result<(), String> {
+        if !self.states.contains_key(&self.entry) {
+            return Err(format!("entry state \"{}\" is not defined", self.entry));
+        }
+        for transition in self.transitions.iter() {
+            if transition.from != "*" && !self.states.contains_key(&transition.from) {
+                return Err(format!("transition from unknown state \"{}\"", transition.from));
+            }
+            if !self.states.contains_key(&transition.to) {
+                return Err(format!("transition to unknown state \"{}\"", transition.to));
+            }
+        }
+        // The rate is clamped between them
+        for (name, state) in self.states.iter() {
+            if state.rate.min > state.rate.max {
+                return Err(format!("state \"{}\" has a rate min over its max", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Named parameters the transitions and playback rates read, set by game code.
+// Booleans are stored as 0 or 1.
+#[derive(Default, Clone, Debug)]
+pub struct AnimationParams(HashMap<String, f32>);
+
+impl AnimationParams {
+    pub fn set(&mut self, name: &str, value: f32) {
+        if let Some(current) = self.0.get_mut(name) {
+            *current = value;
+        } else {
+            self.0.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set(name, if value {1.} else {0.});
+    }
+
+    // Unset parameters read as 0 (false)
+    pub fn get(&self, name: &str) -> f32 {
+        self.0.get(name).copied().unwrap_or(0.)
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.get(name) > 0.5
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, params: &AnimationParams, finished: bool) -> bool {
+        match self {
+            Condition::Greater(name, value) => params.get(name) > *value,
+            Condition::Less(name, value) => params.get(name) < *value,
+            Condition::IsTrue(name) => params.get_bool(name),
+            Condition::IsFalse(name) => !params.get_bool(name),
+            Condition::Finished => finished,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AnimationGraphLoader;
+
+impl AssetLoader for AnimationGraphLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let graph = ron::de::from_bytes::<AnimationGraph>(bytes)?;
+            graph.validate().map_err(|error| {
+                bevy::asset::Error::msg(format!("{:?}: {}", load_context.path(), error))
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(graph));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["animgraph.ron"]
+    }
+}
@@ -0,0 +1,363 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+use crate::{
+    animation::AnimationLink,
+    animation_graph::{AnimationGraph, AnimationParams, GraphState, LoopMode},
+    pose::{BoneMask, BonePose, Pose, PoseBlender, sample_clip, wrap_time, apply_pose, find_bone, blend_override, blend_additive},
+    root_motion::{RootMotion, find_bone_path, clip_root_motion, clip_root_start, to_character_space, pin_root_bone},
+};
+
+// Animation state machine runtime, evaluates an AnimationGraph per entity.
+// Game code sets the named parameters, the graph decides the state and the clips are
+// cross-faded on the skeleton linked through AnimationLink, or on one part of it.
+
+// One playing state, fading in or out
+struct StateLayer {
+    state: String,
+    time: f32,
+    previous_time: f32, // Time at the start of this frame, for root motion
+    weight: f32,
+}
+
+impl StateLayer {
+    // Clip times at the start and end of this frame and how many times the clip looped in between
+    fn span(&self, graph_state: &GraphState, duration: f32, dt: f32, params: &AnimationParams) -> (f32, f32, f32) {
+        match graph_state.loop_mode {
+            LoopMode::Loop => {
+                let previous = wrap_time(self.previous_time, duration);
+                let current = wrap_time(self.time, duration);
+                // Sync groups move the time around, so count the loops from the playback rate
+                let advance = dt * graph_state.rate.evaluate(params);
+                let loops = if duration > 0. {((previous + advance - current) / duration).round()} else {0.};
+                (previous, current, loops)
+            }
+            LoopMode::Once => (self.previous_time, self.time, 0.),
+        }
+    }
+}
+
+// Named event at a normalized time of a clip, like a footstep
+#[derive(Deserialize, Clone, Debug)]
+pub struct ClipEvent {
+    pub name: String,
+    pub time: f32, // 0 is the start of the clip, 1 the end
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerBlend {
+    Override, // Replace the pose below by weight
+    Additive, // Add the clip's motion relative to its first frame
+}
+
+fn default_layer_rate() -> f32 {
+    1.
+}
+
+// A looping clip on top of the graph's states, limited to part of the skeleton,
+// like an emote on the upper body while the legs keep walking
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationLayer {
+    pub name: String,
+    pub clip: String, // Clip name, from the same clips the graph uses
+    pub blend: LayerBlend,
+    #[serde(default)]
+    pub mask: BoneMask,
+    #[serde(default)]
+    pub weight: f32,  // Set by game code, 0 turns the layer off
+    #[serde(default = "default_layer_rate")]
+    pub rate: f32,
+    #[serde(skip)]
+    pub time: f32,
+}
+
+// Fired when playback crosses a ClipEvent
+pub struct AnimationEvent {
+    pub entity: Entity, // The character
+    pub name: String,
+    pub clip: String,
+}
+
+#[derive(Component)]
+pub struct AnimationStateMachine {
+    pub graph: Handle<AnimationGraph>,
+    pub clips: HashMap<String, Handle<AnimationClip>>, // This character's clips, by the names the graph uses
+    pub params: AnimationParams,
+    pub part: Option<String>, // Linked player to drive, None drives all of them
+    pub events: HashMap<String, Vec<ClipEvent>>, // By clip name
+    pub layers: Vec<AnimationLayer>, // Applied in order over the graph's pose
+    state: Option<String>, // None until the graph has loaded
+    fade_duration: f32,    // Blend time of the last transition
+    playing: Vec<StateLayer>, // States playing or fading out
+    bones: HashMap<Entity, HashMap<EntityPath, Entity>>, // Per linked player
+    rest: Pose, // Bones' transforms before the layers first moved them
+}
+
+impl AnimationStateMachine {
+    pub fn new(graph: Handle<AnimationGraph>, clips: HashMap<String, Handle<AnimationClip>>) -> Self {
+        Self {
+            graph,
+            clips,
+            params: AnimationParams::default(),
+            part: None,
+            events: HashMap::default(),
+            layers: Vec::new(),
+            state: None,
+            fade_duration: 0.,
+            playing: Vec::new(),
+            bones: HashMap::default(),
+            rest: Pose::default(),
+        }
+    }
+
+    // Only drive the linked player of this sub-hierarchy
+    pub fn with_part(mut self, part: &str) -> Self {
+        self.part = Some(part.to_string());
+        self
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut AnimationLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    fn clip<'a>(&self, graph: &AnimationGraph, clips: &'a Assets<AnimationClip>, state: &str) -> Option<&'a AnimationClip> {
+        let clip_name = &graph.states.get(state)?.clip;
+        clips.get(self.clips.get(clip_name)?)
+    }
+}
+
+// Update states and fades, then blend the playing clips onto the linked skeleton
+pub fn animation_state_machine_update(
+    time: Res<Time>,
+    graphs: Res<Assets<AnimationGraph>>,
+    clips: Res<Assets<AnimationClip>>,
+    mut machines_query: Query<(Entity, &mut AnimationStateMachine, &AnimationLink, Option<&mut RootMotion>)>,
+    children_query: Query<&Children>,
+    names_query: Query<&Name>,
+    parent_query: Query<&Parent>,
+    global_transforms: Query<&GlobalTransform>,
+    mut transforms: Query<&mut Transform>,
+    mut events: EventWriter<AnimationEvent>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut machine, anim_link, mut root_motion) in machines_query.iter_mut() {
+        let machine = &mut *machine;
+        let graph = match graphs.get(&machine.graph) {
+            Some(graph) => graph,
+            None => continue,
+        };
+
+        // Start in the entry state, also when the graph was reloaded without our state
+        let state_known = machine.state.as_ref().map_or(false, |state| graph.states.contains_key(state));
+        if !state_known {
+            machine.state = Some(graph.entry.clone());
+            machine.playing = vec![StateLayer { state: graph.entry.clone(), time: 0., previous_time: 0., weight: 1. }];
+        }
+        machine.playing.retain(|layer| graph.states.contains_key(&layer.state));
+        let current_state = machine.state.clone().unwrap();
+
+        // Transition, the first one that matches wins.
+        // The new state fades in from where it is if it is still fading out.
+        // Looping clips never finish, their time keeps going past the end
+        let finished = graph.states[&current_state].loop_mode == LoopMode::Once && machine.playing.iter()
+            .find(|layer| layer.state == current_state)
+            .zip(machine.clip(graph, &clips, &current_state))
+            .map_or(false, |(layer, clip)| layer.time >= clip.duration());
+        let transition = graph.transitions.iter().find(|transition| {
+            (transition.from == "*" || transition.from == current_state)
+                && transition.to != current_state
+                && transition.conditions.iter().all(|condition| condition.holds(&machine.params, finished))
+        });
+        if let Some(transition) = transition {
+            machine.state = Some(transition.to.clone());
+            machine.fade_duration = transition.blend;
+            if !machine.playing.iter().any(|layer| layer.state == transition.to) {
+                machine.playing.push(StateLayer { state: transition.to.clone(), time: 0., previous_time: 0., weight: 0. });
+            }
+        }
+        let current_state = machine.state.clone().unwrap();
+
+        // Advance time and fade weights towards the current state
+        let fade_step = if machine.fade_duration > 0. {dt / machine.fade_duration} else {1.};
+        let durations: Vec<Option<f32>> = machine.playing.iter()
+            .map(|layer| machine.clip(graph, &clips, &layer.state).map(|clip| clip.duration()))
+            .collect();
+        for (layer, duration) in machine.playing.iter_mut().zip(durations.iter()) {
+            let graph_state = &graph.states[&layer.state];
+            layer.previous_time = layer.time;
+            layer.time += dt * graph_state.rate.evaluate(&machine.params);
+            if let (LoopMode::Once, Some(duration)) = (graph_state.loop_mode, duration) {
+                layer.time = layer.time.min(*duration);
+            }
+            layer.weight = if layer.state == current_state {
+                (layer.weight + fade_step).min(1.)
+            } else {
+                (layer.weight - fade_step).max(0.)
+            };
+        }
+
+        // Keep states in a sync group at the normalized time of the strongest one
+        let mut leaders: HashMap<&str, (f32, f32)> = HashMap::default(); // Group -> (weight, phase)
+        for (layer, duration) in machine.playing.iter().zip(durations.iter()) {
+            if let (Some(group), Some(duration)) = (&graph.states[&layer.state].sync_group, duration) {
+                if *duration > 0. && leaders.get(group.as_str()).map_or(true, |(weight, _)| layer.weight > *weight) {
+                    leaders.insert(group.as_str(), (layer.weight, wrap_time(layer.time, *duration) / duration));
+                }
+            }
+        }
+        for (layer, duration) in machine.playing.iter_mut().zip(durations.iter()) {
+            if let (Some(group), Some(duration)) = (&graph.states[&layer.state].sync_group, duration) {
+                if let Some((_, phase)) = leaders.get(group.as_str()) {
+                    layer.time = phase * duration;
+                }
+            }
+        }
+        machine.playing.retain(|layer| layer.weight > 0. || layer.state == current_state);
+
+        // Clip events, from the strongest layer only so cross-fading clips don't double them.
+        // An event at t is crossed once for every whole number n with previous < t + n <= current,
+        // counting in normalized time with the loops unwrapped.
+        let strongest = machine.playing.iter()
+            .max_by(|a, b| a.weight.partial_cmp(&b.weight).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some(layer) = strongest {
+            let clip_name = &graph.states[&layer.state].clip;
+            let clip_events = machine.events.get(clip_name);
+            let clip = machine.clip(graph, &clips, &layer.state);
+            if let (Some(clip_events), Some(clip)) = (clip_events, clip) {
+                let duration = clip.duration();
+                if duration > 0. {
+                    let (previous, current, loops) = layer.span(&graph.states[&layer.state], duration, dt, &machine.params);
+                    let previous = previous / duration;
+                    let current = current / duration + loops;
+                    for clip_event in clip_events.iter() {
+                        let crossings = (current - clip_event.time).floor() - (previous - clip_event.time).floor();
+                        for _ in 0..crossings.abs() as u32 {
+                            events.send(AnimationEvent {
+                                entity,
+                                name: clip_event.name.clone(),
+                                clip: clip_name.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        // Root motion: how far the root bone moved in each playing clip this frame, blended like the clips.
+        // Measured in the space of the bone's parent, which is converted to character space with
+        // last frame's global transforms.
+        let mut root_bone = None; // Path, blended start of the clips and parent to character transform
+        if let Some(root_motion) = root_motion.as_deref_mut().filter(|root_motion| root_motion.enabled) {
+            let mut translation = Vec3::ZERO;
+            let mut rotation = Quat::IDENTITY;
+            let mut reference = (Vec3::ZERO, Quat::IDENTITY);
+            let mut total_weight = 0.;
+            let mut path = None;
+            for layer in machine.playing.iter() {
+                let clip = match machine.clip(graph, &clips, &layer.state) {
+                    Some(clip) => clip,
+                    None => continue,
+                };
+                let clip_path = match find_bone_path(clip, &root_motion.bone) {
+                    Some(clip_path) => clip_path,
+                    None => continue,
+                };
+                let (previous, current, loops) = layer.span(&graph.states[&layer.state], clip.duration(), dt, &machine.params);
+                let (layer_translation, layer_rotation) = clip_root_motion(clip, clip_path, previous, current, loops);
+                let (start_translation, start_rotation) = clip_root_start(clip, clip_path);
+                // Running normalized blend, like PoseBlender
+                total_weight += layer.weight;
+                if total_weight > 0. {
+                    let t = layer.weight / total_weight;
+                    translation = translation.lerp(layer_translation, t);
+                    rotation = rotation.slerp(layer_rotation, t);
+                    reference = (reference.0.lerp(start_translation, t), reference.1.slerp(start_rotation, t));
+                }
+                path = Some(clip_path.clone());
+            }
+
+            root_motion.translation = Vec3::ZERO;
+            root_motion.rotation = Quat::IDENTITY;
+            let parent_to_character = path.as_ref()
+                .and_then(|path| anim_link.players.iter()
+                    .filter(|(part, _)| machine.part.as_ref().map_or(true, |driven| driven == *part))
+                    .find_map(|(_, player)| find_bone(*player, path, &children_query, &names_query)))
+                .and_then(|bone| parent_query.get(bone).ok())
+                .and_then(|parent| global_transforms.get(parent.get()).ok())
+                .zip(global_transforms.get(entity).ok())
+                .map(|(parent_global, character_global)| character_global.affine().inverse() * parent_global.affine());
+            if let (Some(path), Some(parent_to_character)) = (path, parent_to_character) {
+                (root_motion.translation, root_motion.rotation) = to_character_space(&parent_to_character, translation, rotation);
+                root_bone = Some((path, reference, parent_to_character));
+            }
+        }
+
+        // Blend the playing clips
+        let mut blender = PoseBlender::default();
+        let mut any_clip = false;
+        for layer in machine.playing.iter() {
+            if let Some(clip) = machine.clip(graph, &clips, &layer.state) {
+                let time = match graph.states[&layer.state].loop_mode {
+                    LoopMode::Loop => wrap_time(layer.time, clip.duration()),
+                    LoopMode::Once => layer.time,
+                };
+                blender.add(&sample_clip(clip, time), layer.weight);
+                any_clip = true;
+            }
+        }
+        let mut pose = blender.finish();
+
+        // Layers over the graph's pose, masked to their bones
+        for layer in machine.layers.iter_mut() {
+            layer.time += dt * layer.rate;
+        }
+        for layer in machine.layers.iter().filter(|layer| layer.weight > 0.) {
+            let clip = match machine.clips.get(&layer.clip).and_then(|clip| clips.get(clip)) {
+                Some(clip) => clip,
+                None => continue,
+            };
+            let layer_pose = sample_clip(clip, wrap_time(layer.time, clip.duration()));
+            // Fading in over bones the graph doesn't animate starts from their rest transform
+            let unseen: Vec<EntityPath> = layer_pose.0.keys().filter(|path| !machine.rest.0.contains_key(*path)).cloned().collect();
+            for path in unseen.iter() {
+                let rest = anim_link.players.iter()
+                    .filter(|(part, _)| machine.part.as_ref().map_or(true, |driven| driven == *part))
+                    .find_map(|(_, player)| find_bone(*player, path, &children_query, &names_query))
+                    .and_then(|bone| transforms.get(bone).ok());
+                if let Some(rest) = rest {
+                    machine.rest.0.insert(path.clone(), BonePose {
+                        translation: Some(rest.translation),
+                        rotation: Some(rest.rotation),
+                        scale: Some(rest.scale),
+                    });
+                }
+            }
+            let weight = layer.weight.min(1.);
+            match layer.blend {
+                LayerBlend::Override => blend_override(&mut pose, &layer_pose, &machine.rest, weight, &layer.mask),
+                LayerBlend::Additive => blend_additive(&mut pose, &layer_pose, &sample_clip(clip, 0.), weight, &layer.mask),
+            }
+            any_clip = true;
+        }
+
+        // Forget the bones of players that are no longer linked, then pose the ones we drive
+        machine.bones.retain(|player, _| anim_link.players.values().any(|linked| linked == player));
+        if any_clip {
+            // The motion moves the character, so keep the root bone itself in place
+            if let Some((path, reference, parent_to_character)) = root_bone {
+                if let Some(bone) = pose.0.get_mut(&path) {
+                    pin_root_bone(bone, reference, &parent_to_character);
+                }
+            }
+            for (part, player) in anim_link.players.iter() {
+                if machine.part.as_ref().map_or(true, |driven| driven == part) {
+                    let bones = machine.bones.entry(*player).or_default();
+                    apply_pose(&pose, *player, bones, &children_query, &names_query, &mut transforms);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+// Behaviour trees for NPC decisions, loaded from .bt.ron files.
+// The tree is ticked from the root every frame. Sequences carry on from the child that was running,
+// selectors try their children in order every tick, so a higher priority branch takes over as soon
+// as it can run. Branches that aren't ticked any more are aborted and start over next time.
+// Leaves read and write the entity's Blackboard. Action leaves are done by game code: the tree puts
+// the action it wants in the BehaviourAction component, game systems start it when it changes and
+// set its status when it is done. One action runs at a time.
+// The running nodes are listed in BehaviourTreeRunner::active, in the inspector.
+
+pub struct BehaviourTreePlugin;
+
+impl Plugin for BehaviourTreePlugin{
+    fn build(&self, app: &mut App) {
+        app.add_asset::<BehaviourTree>()
+        .init_asset_loader::<BehaviourTreeLoader>()
+        .register_type::<Blackboard>()
+        .register_type::<BehaviourTreeRunner>()
+        .register_type::<BehaviourAction>()
+        .add_system(behaviour_tree_update);
+    }
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Success,
+    Failure,
+}
+impl Default for Status
+{
+    fn default() -> Self {
+        Status::Running
+    }
+}
+
+// Named values for the tree and game code to share. Booleans are stored as 0 or 1.
+#[derive(Reflect, Component, Default, Clone, Debug)]
+#[reflect(Component)]
+pub struct Blackboard {
+    pub values: HashMap<String, f32>,
+    pub entities: HashMap<String, Entity>,
+}
+
+impl Blackboard {
+    pub fn set(&mut self, name: &str, value: f32) {
+        if let Some(current) = self.values.get_mut(name) {
+            *current = value;
+        } else {
+            self.values.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set(name, if value {1.} else {0.});
+    }
+
+    // Unset values read as 0 (false)
+    pub fn get(&self, name: &str) -> f32 {
+        self.values.get(name).copied().unwrap_or(0.)
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.get(name) > 0.5
+    }
+
+    pub fn set_entity(&mut self, name: &str, entity: Option<Entity>) {
+        match entity {
+            Some(entity) => {
+                self.entities.insert(name.to_string(), entity);
+            }
+            None => {
+                self.entities.remove(name);
+            }
+        }
+    }
+
+    pub fn entity(&self, name: &str) -> Option<Entity> {
+        self.entities.get(name).copied()
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Check {
+    Greater(String, f32),
+    Less(String, f32),
+    IsTrue(String),
+    IsFalse(String),
+    HasEntity(String),
+}
+
+impl Check {
+    pub fn holds(&self, blackboard: &Blackboard) -> bool {
+        match self {
+            Check::Greater(name, value) => blackboard.get(name) > *value,
+            Check::Less(name, value) => blackboard.get(name) < *value,
+            Check::IsTrue(name) => blackboard.get_bool(name),
+            Check::IsFalse(name) => !blackboard.get_bool(name),
+            Check::HasEntity(name) => blackboard.entity(name).is_some(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum ParallelPolicy {
+    RequireOne, // Succeeds when one child succeeds, fails when all fail
+    RequireAll, // Succeeds when all children succeed, fails when one fails
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Decorator {
+    Invert,
+    Succeed,       // Whatever the child finishes with
+    Repeat(u32),   // Run the child this many times, 0 for ever. Fails if the child does.
+    UntilFail,     // Run the child until it fails, then succeed
+    Timeout(f32),  // Fail if the child runs longer than this many seconds
+    Cooldown(f32), // Fail for this many seconds after the child finished or was aborted, unless it failed without running
+    While(Check),  // Fail as soon as the check doesn't hold, checked every tick
+}
+
+// Tree as written in the file, nested
+#[derive(Deserialize, Clone, Debug)]
+pub enum NodeDefinition {
+    Sequence(Vec<NodeDefinition>),
+    Selector(Vec<NodeDefinition>),
+    Parallel(ParallelPolicy, Vec<NodeDefinition>),
+    Decorator(Decorator, Box<NodeDefinition>),
+    Action(String),    // Done by game code, see BehaviourAction
+    Condition(Check),
+    Wait(f32),         // Seconds
+    Set(String, f32),  // Write a blackboard value
+}
+
+#[derive(Clone, Debug)]
+pub enum NodeKind {
+    Sequence,
+    Selector,
+    Parallel(ParallelPolicy),
+    Decorator(Decorator),
+    Action(String),
+    Condition(Check),
+    Wait(f32),
+    Set(String, f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+    pub kind: NodeKind,
+    pub children: Vec<usize>,
+    pub depth: usize,
+}
+
+// Tree flattened in depth first order, the root is the first node
+#[derive(TypeUuid, Debug)]
+#[uuid = "d3a9e6f2-41b7-4c85-b0e1-6f2c8a7d5b94"]
+pub struct BehaviourTree {
+    pub nodes: Vec<TreeNode>,
+}
+
+impl BehaviourTree {
+    pub fn new(root: &NodeDefinition) -> Self {
+        let mut tree = Self {nodes: Vec::new()};
+        tree.add(root, 0);
+        tree
+    }
+
+    fn add(&mut self, definition: &NodeDefinition, depth: usize) -> usize {
+        let (kind, children): (NodeKind, Vec<&NodeDefinition>) = match definition {
+            NodeDefinition::Sequence(children) => (NodeKind::Sequence, children.iter().collect()),
+            NodeDefinition::Selector(children) => (NodeKind::Selector, children.iter().collect()),
+            NodeDefinition::Parallel(policy, children) => (NodeKind::Parallel(*policy), children.iter().collect()),
+            NodeDefinition::Decorator(decorator, child) => (NodeKind::Decorator(decorator.clone()), vec![child.as_ref()]),
+            NodeDefinition::Action(name) => (NodeKind::Action(name.clone()), Vec::new()),
+            NodeDefinition::Condition(check) => (NodeKind::Condition(check.clone()), Vec::new()),
+            NodeDefinition::Wait(seconds) => (NodeKind::Wait(*seconds), Vec::new()),
+            NodeDefinition::Set(name, value) => (NodeKind::Set(name.clone(), *value), Vec::new()),
+        };
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode {kind, children: Vec::new(), depth});
+        let children = children.into_iter().map(|child| self.add(child, depth + 1)).collect();
+        self.nodes[index].children = children;
+        index
+    }
+}
+
+#[derive(Default)]
+pub struct BehaviourTreeLoader;
+
+impl AssetLoader for BehaviourTreeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let root = ron::de::from_bytes::<NodeDefinition>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(BehaviourTree::new(&root)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bt.ron"]
+    }
+}
+
+// The action the tree wants done, set by the tree. Game code sets the status when it is done.
+// An empty name is no action.
+#[derive(Reflect, Component, Default, Clone, Debug)]
+#[reflect(Component)]
+pub struct BehaviourAction {
+    pub name: String,
+    pub status: Status,
+}
+
+// Per node state of a running tree
+#[derive(Clone, Default)]
+struct NodeState {
+    running: bool,
+    tick: u32,          // Last tick the node ran, running nodes that missed a tick were aborted
+    child: usize,       // Sequence: the child running
+    finished: Vec<Option<Status>>, // Parallel: how the children finished
+    timer: f32,
+    count: u32,
+    cooldown_end: f64,  // Kept when the node starts over
+}
+
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+pub struct BehaviourTreeRunner {
+    pub tree: Handle<BehaviourTree>,
+    pub enabled: bool,
+    pub status: Status,      // Of the root, last tick
+    pub active: Vec<String>, // Running nodes, indented by depth
+    #[reflect(ignore)]
+    states: Vec<NodeState>,
+    #[reflect(ignore)]
+    tick: u32,
+}
+
+impl BehaviourTreeRunner {
+    pub fn new(tree: Handle<BehaviourTree>) -> Self {
+        Self {
+            tree,
+            enabled: true,
+            ..default()
+        }
+    }
+}
+
+struct TreeTick<'a> {
+    nodes: &'a [TreeNode],
+    states: &'a mut [NodeState],
+    blackboard: &'a mut Blackboard,
+    action: &'a BehaviourAction,
+    action_consumed: bool,     // The finished action's status was handed to its leaf
+    requested: Option<String>, // Action a leaf wants running
+    restart: bool,             // It is the action that just finished, start it again
+    dt: f32,
+    now: f64,
+    tick: u32,
+}
+
+impl<'a> TreeTick<'a> {
+    fn run(&mut self, index: usize) -> Status {
+        let nodes = self.nodes;
+        let node = &nodes[index];
+        if !self.states[index].running {
+            let cooldown_end = self.states[index].cooldown_end;
+            self.states[index] = NodeState {
+                cooldown_end,
+                finished: vec![None; node.children.len()],
+                ..default()
+            };
+        }
+        self.states[index].tick = self.tick;
+
+        let status = match &node.kind {
+            NodeKind::Sequence => {
+                let mut status = Status::Success;
+                while self.states[index].child < node.children.len() {
+                    match self.run(node.children[self.states[index].child]) {
+                        Status::Success => self.states[index].child += 1,
+                        other => {
+                            status = other;
+                            break;
+                        }
+                    }
+                }
+                status
+            }
+            NodeKind::Selector => node.children.iter()
+                .map(|child| self.run(*child))
+                .find(|status| *status != Status::Failure)
+                .unwrap_or(Status::Failure),
+            NodeKind::Parallel(policy) => {
+                for (i, child) in node.children.iter().enumerate() {
+                    if self.states[index].finished[i].is_none() {
+                        let status = self.run(*child);
+                        if status != Status::Running {
+                            self.states[index].finished[i] = Some(status);
+                        }
+                    }
+                }
+                let finished = &self.states[index].finished;
+                let successes = finished.iter().filter(|status| **status == Some(Status::Success)).count();
+                let failures = finished.iter().filter(|status| **status == Some(Status::Failure)).count();
+                match policy {
+                    ParallelPolicy::RequireOne if successes > 0 => Status::Success,
+                    ParallelPolicy::RequireOne if failures == finished.len() => Status::Failure,
+                    ParallelPolicy::RequireAll if failures > 0 => Status::Failure,
+                    ParallelPolicy::RequireAll if successes == finished.len() => Status::Success,
+                    _ => Status::Running,
+                }
+            }
+            NodeKind::Decorator(decorator) => self.run_decorator(index, decorator, node.children[0]),
+            NodeKind::Action(name) => {
+                if !self.action_consumed && self.action.name == *name && self.action.status != Status::Running {
+                    self.action_consumed = true;
+                    self.action.status
+                } else {
+                    if self.requested.is_none() {
+                        self.restart = self.action.name == *name && self.action.status != Status::Running;
+                        self.requested = Some(name.clone());
+                    }
+                    Status::Running
+                }
+            }
+            NodeKind::Condition(check) => if check.holds(self.blackboard) {Status::Success} else {Status::Failure},
+            NodeKind::Wait(seconds) => {
+                self.states[index].timer += self.dt;
+                if self.states[index].timer >= *seconds {Status::Success} else {Status::Running}
+            }
+            NodeKind::Set(name, value) => {
+                self.blackboard.set(name, *value);
+                Status::Success
+            }
+        };
+
+        self.states[index].running = status == Status::Running;
+        status
+    }
+
+    fn run_decorator(&mut self, index: usize, decorator: &Decorator, child: usize) -> Status {
+        match decorator {
+            Decorator::Invert => match self.run(child) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Decorator::Succeed => match self.run(child) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            // Once per tick at most, the child starts over next tick
+            Decorator::Repeat(times) => match self.run(child) {
+                Status::Failure => Status::Failure,
+                Status::Success => {
+                    self.states[index].count += 1;
+                    if *times > 0 && self.states[index].count >= *times {Status::Success} else {Status::Running}
+                }
+                Status::Running => Status::Running,
+            },
+            Decorator::UntilFail => match self.run(child) {
+                Status::Failure => Status::Success,
+                _ => Status::Running,
+            },
+            Decorator::Timeout(seconds) => {
+                self.states[index].timer += self.dt;
+                if self.states[index].timer > *seconds {Status::Failure} else {self.run(child)}
+            }
+            Decorator::Cooldown(seconds) => {
+                if self.now < self.states[index].cooldown_end {
+                    return Status::Failure;
+                }
+                // A child that fails straight away, like a While that doesn't hold, never got going
+                let was_running = self.states[index].running;
+                let status = self.run(child);
+                if status == Status::Success || (status == Status::Failure && was_running) {
+                    self.states[index].cooldown_end = self.now + *seconds as f64;
+                }
+                status
+            }
+            Decorator::While(check) => if check.holds(self.blackboard) {self.run(child)} else {Status::Failure},
+        }
+    }
+
+    // Abort what didn't run this tick
+    fn abort_unticked(&mut self) {
+        let (tick, now) = (self.tick, self.now);
+        for (node, state) in self.nodes.iter().zip(self.states.iter_mut()) {
+            if state.running && state.tick != tick {
+                state.running = false;
+                // Or a higher priority branch taking over now and then would keep it from ever cooling down
+                if let NodeKind::Decorator(Decorator::Cooldown(seconds)) = &node.kind {
+                    state.cooldown_end = now + *seconds as f64;
+                }
+            }
+        }
+    }
+}
+
+pub fn behaviour_tree_update(
+    time: Res<Time>,
+    trees: Res<Assets<BehaviourTree>>,
+    mut query: Query<(&mut BehaviourTreeRunner, &mut Blackboard, &mut BehaviourAction)>,
+){
+    for (mut runner, mut blackboard, mut action) in query.iter_mut() {
+        if !runner.enabled {
+            continue;
+        }
+        let tree = match trees.get(&runner.tree) {
+            Some(tree) => tree,
+            None => continue,
+        };
+        let runner = runner.as_mut();
+        if runner.states.len() != tree.nodes.len() {
+            runner.states = vec![NodeState::default(); tree.nodes.len()]; // New or reloaded tree
+        }
+        runner.tick = runner.tick.wrapping_add(1);
+
+        let mut tree_tick = TreeTick {
+            nodes: &tree.nodes,
+            states: &mut runner.states,
+            blackboard: blackboard.as_mut(),
+            action: &action,
+            action_consumed: false,
+            requested: None,
+            restart: false,
+            dt: time.delta_seconds(),
+            now: time.elapsed_seconds_f64(),
+            tick: runner.tick,
+        };
+        runner.status = tree_tick.run(0);
+        tree_tick.abort_unticked();
+        let (requested, restart) = (tree_tick.requested, tree_tick.restart);
+
+        runner.active = tree.nodes.iter().zip(runner.states.iter())
+            .filter(|(_, state)| state.running)
+            .map(|(node, _)| format!("{}{:?}", "  ".repeat(node.depth), node.kind))
+            .collect();
+
+        // Only touch the action when it changes, game code starts actions on change
+        match requested {
+            Some(name) => if restart || action.name != name {
+                *action = BehaviourAction {name, status: Status::Running};
+            },
+            None => if !action.name.is_empty() {
+                *action = BehaviourAction::default();
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.1;
+
+    // The fox's tree, cut down
+    const FOX_TREE: &str = r#"Selector([
+        Decorator(While(Less("player_distance", 1.5)),
+            Action("flee")),
+        Decorator(Cooldown(10.0),
+            Decorator(Timeout(8.0),
+                Decorator(While(Less("player_distance", 6.0)),
+                    Action("follow")))),
+        Action("idle"),
+    ])"#;
+
+    // Ticks a tree like behaviour_tree_update, DT seconds apart
+    struct TestRunner {
+        tree: BehaviourTree,
+        states: Vec<NodeState>,
+        blackboard: Blackboard,
+        action: BehaviourAction,
+        tick: u32,
+    }
+
+    impl TestRunner {
+        fn new(source: &str) -> Self {
+            let tree = BehaviourTree::new(&ron::de::from_str(source).unwrap());
+            let states = vec![NodeState::default(); tree.nodes.len()];
+            Self {tree, states, blackboard: Blackboard::default(), action: BehaviourAction::default(), tick: 0}
+        }
+
+        // The action requested
+        fn tick(&mut self) -> Option<String> {
+            self.tick += 1;
+            let mut tree_tick = TreeTick {
+                nodes: &self.tree.nodes,
+                states: &mut self.states,
+                blackboard: &mut self.blackboard,
+                action: &self.action,
+                action_consumed: false,
+                requested: None,
+                restart: false,
+                dt: DT,
+                now: self.tick as f64 * DT as f64,
+                tick: self.tick,
+            };
+            tree_tick.run(0);
+            tree_tick.abort_unticked();
+            let requested = tree_tick.requested;
+            match &requested {
+                Some(name) => if self.action.name != *name {
+                    self.action = BehaviourAction {name: name.clone(), status: Status::Running};
+                },
+                None => self.action = BehaviourAction::default(),
+            }
+            requested
+        }
+    }
+
+    #[test]
+    fn cooldown_after_child_succeeds() {
+        let mut runner = TestRunner::new(FOX_TREE);
+        runner.blackboard.set("player_distance", 4.);
+        assert_eq!(runner.tick().as_deref(), Some("follow"));
+        runner.action.status = Status::Success;
+        assert_eq!(runner.tick(), None);
+        assert_eq!(runner.tick().as_deref(), Some("idle"));
+    }
+
+    #[test]
+    fn no_cooldown_when_child_fails_without_running() {
+        let mut runner = TestRunner::new(FOX_TREE);
+        runner.blackboard.set("player_distance", 10.);
+        assert_eq!(runner.tick().as_deref(), Some("idle"));
+        runner.blackboard.set("player_distance", 4.);
+        assert_eq!(runner.tick().as_deref(), Some("follow"));
+    }
+
+    #[test]
+    fn cooldown_after_child_is_aborted() {
+        let mut runner = TestRunner::new(FOX_TREE);
+        runner.blackboard.set("player_distance", 4.);
+        assert_eq!(runner.tick().as_deref(), Some("follow"));
+        // Flee takes over
+        runner.blackboard.set("player_distance", 1.);
+        assert_eq!(runner.tick().as_deref(), Some("flee"));
+        runner.blackboard.set("player_distance", 4.);
+        assert_eq!(runner.tick().as_deref(), Some("idle"));
+        // Until the ten seconds are over
+        for _ in 0..100 {
+            runner.tick();
+        }
+        assert_eq!(runner.tick().as_deref(), Some("follow"));
+    }
+}
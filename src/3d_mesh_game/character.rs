@@ -0,0 +1,254 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    gltf::Gltf,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+use serde::Deserialize;
+use crate::{
+    Speed,
+    animation_graph::AnimationGraph,
+    animation_state::{AnimationLayer, AnimationStateMachine, ClipEvent},
+    controller::CharacterController,
+    foot_ik::FootIk,
+    look_at::LookAtTarget,
+    root_motion::RootMotion,
+    stride::Strides,
+};
+
+// Character definition asset, loaded from .character.ron sidecar files next to the models.
+// Names the glTF model and how to import it (scale, which way it faces), the animation graph,
+// which glTF animation plays each clip role of the graph, and the collision capsule.
+// Clips are looked up by their glTF names, so re-exporting the model in a different
+// animation order doesn't change what plays.
+// Spawn a CharacterBundle and the rest is added once the definition has loaded. The character
+// entity always faces -Z with +Y up and has scale 1, the model is a child corrected to match.
+// Characters with a Speed get their locomotion animated from it, whoever sets the speed.
+
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<CharacterCapsule>()
+        .add_asset::<CharacterDefinition>()
+        .init_asset_loader::<CharacterDefinitionLoader>()
+        .add_system(character_setup)
+        .add_system(character_locomotion);
+    }
+}
+
+// Vertical collision capsule, standing on the character's origin
+#[derive(Reflect, Component, Deserialize, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct CharacterCapsule {
+    pub radius: f32,
+    pub height: f32, // Total, including the rounded ends
+}
+impl Default for CharacterCapsule
+{
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            height: 1.8,
+        }
+    }
+}
+
+fn default_scale() -> f32 {
+    1.
+}
+
+fn default_forward() -> Vec3 {
+    Vec3::NEG_Z
+}
+
+fn default_up() -> Vec3 {
+    Vec3::Y
+}
+
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "8c1f5a27-3e9b-4d60-a2c4-71b6e0d9f358"]
+pub struct CharacterDefinition {
+    pub model: String,                  // glTF file, relative to the assets folder
+    #[serde(default = "default_scale")]
+    pub scale: f32,                     // Import scale of the model
+    #[serde(default = "default_forward")]
+    pub forward: Vec3,                  // Direction the model faces, in its own axes
+    #[serde(default = "default_up")]
+    pub up: Vec3,
+    #[serde(default)]
+    pub capsule: CharacterCapsule,
+    pub animation_graph: String,
+    pub clips: HashMap<String, String>, // Clip role (the name the graph uses) -> glTF animation name
+    #[serde(default)]
+    pub root_motion_bone: Option<String>, // Bone whose travel can drive the character, see root_motion.rs
+    #[serde(default)]
+    pub feet: Vec<String>,                // Foot bone names, for stride matching, see stride.rs
+    #[serde(default)]
+    pub locomotion_clips: Vec<String>,    // Graph clip names to measure the stride of
+    #[serde(default)]
+    pub events: HashMap<String, Vec<ClipEvent>>, // Graph clip name -> events, see AnimationEvent
+    #[serde(default)]
+    pub layers: Vec<AnimationLayer>,      // Masked clips over the graph, see animation_state.rs
+    #[serde(default)]
+    pub foot_ik: Option<FootIk>,
+    #[serde(default)]
+    pub look_at: Option<LookAtTarget>,
+    #[serde(skip)]
+    pub gltf: Handle<Gltf>,
+    #[serde(skip)]
+    pub graph: Handle<AnimationGraph>,
+}
+
+impl CharacterDefinition {
+    // Rotation that turns the model to face -Z with +Y up
+    pub fn axis_correction(&self) -> Quat {
+        let forward = self.forward.normalize();
+        let up = self.up.normalize();
+        let right = forward.cross(up);
+        Quat::from_mat3(&Mat3::from_cols(right, up, -forward)).inverse()
+    }
+}
+
+// Spawn a character from a definition, the scene and animations are added once it has loaded
+#[derive(Component)]
+pub struct Character(pub Handle<CharacterDefinition>);
+
+#[derive(Bundle)]
+pub struct CharacterBundle {
+    pub character: Character,
+    pub spatial: SpatialBundle,
+}
+
+impl CharacterBundle {
+    pub fn new(definition: Handle<CharacterDefinition>, transform: Transform) -> Self {
+        Self {
+            character: Character(definition),
+            spatial: SpatialBundle {
+                transform,
+                ..default()
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CharacterDefinitionLoader;
+
+impl AssetLoader for CharacterDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut definition = ron::de::from_bytes::<CharacterDefinition>(bytes)?;
+            let model_path = AssetPath::new(definition.model.clone().into(), None);
+            let graph_path = AssetPath::new(definition.animation_graph.clone().into(), None);
+            definition.gltf = load_context.get_handle(model_path.clone());
+            definition.graph = load_context.get_handle(graph_path.clone());
+            load_context.set_default_asset(LoadedAsset::new(definition)
+                .with_dependencies(vec![model_path, graph_path]));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["character.ron"]
+    }
+}
+
+// Look up the clips of a definition in its glTF by name.
+// Clips that can't be found are left out and reported, listing the names the glTF does have.
+fn resolve_clips(definition: &CharacterDefinition, gltf: &Gltf) -> HashMap<String, Handle<AnimationClip>> {
+    let mut clips = HashMap::default();
+    for (clip_name, animation_name) in definition.clips.iter() {
+        match gltf.named_animations.get(animation_name) {
+            Some(clip) => {
+                clips.insert(clip_name.clone(), clip.clone());
+            }
+            None => {
+                let mut available: Vec<&str> = gltf.named_animations.keys().map(|name| name.as_str()).collect();
+                available.sort();
+                error!("Character clip \"{}\" wants animation \"{}\", which {} doesn't have. Available animations: {:?}",
+                    clip_name, animation_name, definition.model, available);
+            }
+        }
+    }
+    clips
+}
+
+// Once a character's definition and model have loaded, spawn its scene and animation state machine
+fn character_setup(
+    mut commands: Commands,
+    definitions: Res<Assets<CharacterDefinition>>,
+    gltfs: Res<Assets<Gltf>>,
+    characters_query: Query<(Entity, &Character), Without<AnimationStateMachine>>,
+){
+    for (entity, character) in characters_query.iter() {
+        let definition = match definitions.get(&character.0) {
+            Some(definition) => definition,
+            None => continue,
+        };
+        let gltf = match gltfs.get(&definition.gltf) {
+            Some(gltf) => gltf,
+            None => continue,
+        };
+
+        // The model as a child, scaled and turned to the character's axes
+        match gltf.default_scene.as_ref().or(gltf.scenes.first()) {
+            Some(scene) => {
+                let model = commands.spawn((
+                    SceneBundle {
+                        scene: scene.clone(),
+                        transform: Transform {
+                            rotation: definition.axis_correction(),
+                            scale: Vec3::splat(definition.scale),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Name::new("Model"),
+                )).id();
+                commands.entity(entity).add_child(model);
+            }
+            None => error!("{} has no scene to spawn for the character", definition.model),
+        }
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert(definition.capsule);
+        if let Some(foot_ik) = &definition.foot_ik {
+            entity_commands.insert(foot_ik.clone());
+        }
+        if let Some(look_at) = &definition.look_at {
+            entity_commands.insert(look_at.clone());
+        }
+        if let Some(bone) = &definition.root_motion_bone {
+            entity_commands.insert(RootMotion::new(bone));
+        }
+        let mut state_machine = AnimationStateMachine::new(definition.graph.clone(), resolve_clips(definition, gltf));
+        state_machine.events = definition.events.clone();
+        state_machine.layers = definition.layers.clone();
+        entity_commands.insert(state_machine);
+    }
+}
+
+
+// Feed the animation graph the locomotion parameters, the graph picks and blends the clips.
+// Clips play at the rate that matches their stride to our speed, as <clip>_rate,
+// or as authored if their stride wasn't measured (yet).
+fn character_locomotion(
+    mut query: Query<(&Speed, &mut AnimationStateMachine, Option<&Strides>, Option<&CharacterController>)>,
+){
+    for (speed, mut state_machine, strides, controller) in query.iter_mut() {
+        let ground_speed = speed.0.length();
+        let state_machine = state_machine.as_mut();
+        state_machine.params.set("speed", ground_speed);
+        state_machine.params.set_bool("grounded", controller.map_or(true, |controller| controller.grounded));
+        for clip in state_machine.clips.keys() {
+            let stride = strides.and_then(|strides| strides.0.get(clip).copied().flatten());
+            state_machine.params.set(&format!("{}_rate", clip), stride.map_or(1., |stride| stride.rate(ground_speed)));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+use bevy_playground::collision::{Capsule, Collider, ControllerSettings, move_character};
+use crate::{
+    Speed, SystemOrder,
+    character::CharacterCapsule,
+    root_motion::RootMotion,
+};
+
+// Kinematic character controller, moves characters with their capsule against the static colliders.
+// Game code sets Speed (or turns on root motion), the controller adds gravity, collides, slides,
+// steps and keeps to the ground, and publishes grounded and the ground normal.
+// Hard landings and running into things are sent as CharacterImpact events.
+// The collision itself is in bevy_playground::collision.
+
+pub struct CharacterControllerPlugin;
+
+impl Plugin for CharacterControllerPlugin{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionWorld>()
+        .add_event::<CharacterImpact>()
+        .register_type::<CharacterController>()
+        .add_system(collision_world_update.before(character_controller_update))
+        .add_system(character_controller_update
+            .after(SystemOrder::PlayerMovement)
+            .before(SystemOrder::CameraMovement));
+    }
+}
+
+// Below this the character fell off the world and starts over
+const FALL_LIMIT: f32 = -20.;
+// Slower impacts aren't worth an event, like stepping down or brushing along a wall
+const IMPACT_MIN_SPEED: f32 = 1.5;
+
+// Static geometry to collide with, a box from the entity's bounding box or the triangles of its mesh
+#[derive(Component, Clone, Copy, Debug)]
+pub enum StaticCollider {
+    Box,
+    Mesh,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImpactKind {
+    Landed,
+    Collided,
+}
+
+pub struct CharacterImpact {
+    pub entity: Entity,
+    pub kind: ImpactKind,
+    pub speed: f32, // Lost in the impact
+}
+
+// All static colliders in world space
+#[derive(Resource, Default)]
+pub struct CollisionWorld(pub Vec<Collider>);
+
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct CharacterController {
+    pub gravity: f32,
+    pub max_slope: f32,     // Radians
+    pub step_height: f32,
+    pub snap_distance: f32,
+    pub vertical_speed: f32,
+    // Results of the last move, for other systems to read
+    pub grounded: bool,
+    pub ground_normal: Vec3,
+    pub blocked: bool,      // Something stopped the horizontal motion
+}
+impl Default for CharacterController
+{
+    fn default() -> Self {
+        let settings = ControllerSettings::default();
+        Self {
+            gravity: 9.81,
+            max_slope: settings.max_slope,
+            step_height: settings.step_height,
+            snap_distance: settings.snap_distance,
+            vertical_speed: 0.,
+            grounded: false,
+            ground_normal: Vec3::Y,
+            blocked: false,
+        }
+    }
+}
+
+// Rebuilt every frame, the playground scenes are small and things may still be loading or moving
+pub fn collision_world_update(
+    mut collision_world: ResMut<CollisionWorld>,
+    meshes: Res<Assets<Mesh>>,
+    colliders_query: Query<(&StaticCollider, &GlobalTransform, Option<&Aabb>, Option<&Handle<Mesh>>)>,
+){
+    collision_world.0.clear();
+    for (collider, transform, aabb, mesh) in colliders_query.iter() {
+        match collider {
+            StaticCollider::Box => if let Some(aabb) = aabb {
+                collision_world.0.push(Collider::from_aabb(transform, aabb.center.into(), aabb.half_extents.into()));
+            },
+            StaticCollider::Mesh => if let Some(mesh) = mesh.and_then(|mesh| meshes.get(mesh)) {
+                collision_world.0.extend(Collider::from_mesh(mesh, transform));
+            },
+        }
+    }
+}
+
+pub fn character_controller_update(
+    time: Res<Time>,
+    collision_world: Res<CollisionWorld>,
+    mut impact_events: EventWriter<CharacterImpact>,
+    mut query: Query<(Entity, &mut CharacterController, &CharacterCapsule, &mut Speed, &mut Transform, Option<&RootMotion>)>,
+){
+    let dt = time.delta_seconds();
+    for (entity, mut controller, capsule, mut speed, mut transform, root_motion) in query.iter_mut() {
+        let settings = ControllerSettings {
+            max_slope: controller.max_slope,
+            step_height: controller.step_height,
+            snap_distance: controller.snap_distance,
+            ..default()
+        };
+        // With root motion the clips say how far to go, last frame's motion is the latest there is
+        let horizontal = match root_motion.filter(|root_motion| root_motion.enabled) {
+            Some(root_motion) => transform.rotation * root_motion.translation,
+            None => Vec3::new(speed.0.x, 0., speed.0.z) * dt,
+        };
+        controller.vertical_speed -= controller.gravity * dt;
+        let motion = horizontal + Vec3::Y * controller.vertical_speed * dt;
+
+        let result = move_character(
+            &collision_world.0,
+            &Capsule {radius: capsule.radius, height: capsule.height},
+            &settings,
+            transform.translation,
+            motion,
+            controller.grounded);
+        // Speed lost to the ground and to walls, once when it happens
+        if dt > 0. {
+            let fall_speed = -controller.vertical_speed;
+            if result.grounded && !controller.grounded && fall_speed > IMPACT_MIN_SPEED {
+                impact_events.send(CharacterImpact {entity, kind: ImpactKind::Landed, speed: fall_speed});
+            }
+            let moved = result.position - transform.translation;
+            let blocked_speed = Vec3::new(horizontal.x - moved.x, 0., horizontal.z - moved.z).length() / dt;
+            let blocked = blocked_speed > IMPACT_MIN_SPEED;
+            if blocked && !controller.blocked {
+                impact_events.send(CharacterImpact {entity, kind: ImpactKind::Collided, speed: blocked_speed});
+            }
+            controller.blocked = blocked;
+        }
+        transform.translation = result.position;
+        controller.grounded = result.grounded;
+        controller.ground_normal = result.ground_normal;
+        if result.grounded {
+            controller.vertical_speed = 0.;
+        } else if result.hit_ceiling {
+            controller.vertical_speed = controller.vertical_speed.min(0.);
+        }
+
+        if transform.translation.y < FALL_LIMIT {
+            transform.translation = Vec3::new(0., 1., 0.);
+            speed.0 = Vec3::ZERO;
+            controller.vertical_speed = 0.;
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_prototype_debug_lines::*;
+use bevy_playground::collision::ray_cast;
+use serde::Deserialize;
+use crate::{
+    animation::AnimationLink,
+    animation_state::animation_state_machine_update,
+    controller::CollisionWorld,
+    pose::{find_named_descendant, world_affine, world_rotation, rotate_bone_world},
+    root_motion::root_motion_apply,
+};
+
+// Two-bone foot IK, plants the animated feet on uneven ground.
+// Runs on the freshly animated pose each frame: casts a ray down from each foot, lowers the pelvis
+// so the lowest foot can reach, then bends hip and knee so the foot lands on the ground,
+// keeping the height the animation lifts it by. The bones are found by name in the scene.
+
+pub struct FootIkPlugin;
+
+impl Plugin for FootIkPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<FootIk>()
+        .add_system(foot_ik_toggle)
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            foot_ik_update
+                .after(animation_state_machine_update)
+                .after(root_motion_apply)
+                .before(TransformSystem::TransformPropagate));
+    }
+}
+
+// How far above the foot the ground ray starts, and how far below it the ground can be
+const IK_RAY_HEIGHT: f32 = 0.5;
+const IK_MAX_REACH: f32 = 0.5;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Reflect, FromReflect, Deserialize, Clone, Debug, Default)]
+pub struct IkLeg {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub hip: String,  // Upper leg bone
+    pub knee: String, // Lower leg bone
+    pub foot: String,
+}
+
+#[derive(Reflect, Component, Deserialize, Clone, Debug)]
+#[reflect(Component)]
+#[serde(default)]
+pub struct FootIk {
+    pub enabled: bool,
+    pub pelvis: String,        // Bone that moves the whole body, lowered to let the feet reach
+    pub legs: Vec<IkLeg>,
+    pub max_pelvis_drop: f32,
+    pub pelvis_smoothing: f32, // Fraction of the pelvis offset left after a second
+    pub debug: bool,           // Draw the legs, rays and targets
+    #[serde(skip)]
+    pelvis_offset: f32,
+}
+impl Default for FootIk
+{
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pelvis: String::new(),
+            legs: Vec::new(),
+            max_pelvis_drop: 0.3,
+            pelvis_smoothing: 0.001,
+            debug: false,
+            pelvis_offset: 0.,
+        }
+    }
+}
+
+// Analytic two-bone IK. a, b, c are the hip, knee and foot positions, target where the foot should go.
+// Returns the world space rotations to apply to the hip and then the knee: the knee opens or closes
+// to get the right hip to foot distance, then the hip swings the leg towards the target.
+// The leg keeps bending in the plane it is bent in, bend_fallback is used when it is straight.
+pub fn solve_two_bone(a: Vec3, b: Vec3, c: Vec3, target: Vec3, bend_fallback: Vec3) -> (Quat, Quat) {
+    let epsilon = 0.0001;
+    let length_ab = (b - a).length();
+    let length_cb = (c - b).length();
+    let length_at = (target - a).length().clamp(epsilon, length_ab + length_cb - epsilon);
+
+    let angle = |u: Vec3, v: Vec3| u.normalize_or_zero().dot(v.normalize_or_zero()).clamp(-1., 1.).acos();
+    // Current and wanted angles at the hip (between hip-foot and hip-knee) and at the knee
+    let hip_angle = angle(c - a, b - a);
+    let knee_angle = angle(a - b, c - b);
+    let hip_angle_wanted = ((length_cb * length_cb - length_ab * length_ab - length_at * length_at)
+        / (-2. * length_ab * length_at)).clamp(-1., 1.).acos();
+    let knee_angle_wanted = ((length_at * length_at - length_ab * length_ab - length_cb * length_cb)
+        / (-2. * length_ab * length_cb)).clamp(-1., 1.).acos();
+
+    let mut bend_axis = (c - a).cross(b - a).normalize_or_zero();
+    if bend_axis == Vec3::ZERO {
+        bend_axis = bend_fallback;
+    }
+    let swing_axis = (c - a).cross(target - a).normalize_or_zero();
+
+    let hip_bend = Quat::from_axis_angle(bend_axis, hip_angle_wanted - hip_angle);
+    let knee_bend = Quat::from_axis_angle(bend_axis, knee_angle_wanted - knee_angle);
+    let swing = if swing_axis == Vec3::ZERO {Quat::IDENTITY} else {Quat::from_axis_angle(swing_axis, angle(c - a, target - a))};
+    // The hip rotation also turns the knee and its bend axis, so bend the knee in the moved leg
+    let hip_delta = swing * hip_bend;
+    (hip_delta, hip_delta * knee_bend * hip_delta.inverse())
+}
+
+struct LegBones {
+    hip: Entity,
+    knee: Entity,
+    foot: Entity,
+}
+
+fn foot_ik_toggle(
+    kb_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut FootIk>,
+){
+    if kb_input.just_pressed(KeyCode::I) {
+        for mut foot_ik in query.iter_mut() {
+            foot_ik.enabled = !foot_ik.enabled;
+        }
+    }
+}
+
+pub fn foot_ik_update(
+    time: Res<Time>,
+    mut lines: ResMut<DebugLines>,
+    mut characters_query: Query<(Entity, &mut FootIk, &AnimationLink)>,
+    collision_world: Res<CollisionWorld>,
+    children_query: Query<&Children>,
+    names_query: Query<&Name>,
+    parent_query: Query<&Parent>,
+    mut transforms: Query<&mut Transform>,
+){
+    let dt = time.delta_seconds();
+    for (character, mut foot_ik, anim_link) in characters_query.iter_mut() {
+        if !foot_ik.enabled {
+            continue;
+        }
+        let find_bone = |name: &str| anim_link.players.values()
+            .find_map(|player| find_named_descendant(*player, name, &children_query, &names_query));
+        let pelvis = match find_bone(&foot_ik.pelvis) {
+            Some(pelvis) => pelvis,
+            None => continue, // Scene not spawned yet
+        };
+        let legs: Vec<LegBones> = foot_ik.legs.iter()
+            .filter(|leg| leg.enabled)
+            .filter_map(|leg| Some(LegBones {
+                hip: find_bone(&leg.hip)?,
+                knee: find_bone(&leg.knee)?,
+                foot: find_bone(&leg.foot)?,
+            }))
+            .collect();
+        let character_transform = match world_affine(character, &transforms, &parent_query) {
+            Some(affine) => affine,
+            None => continue,
+        };
+        let floor_height = character_transform.translation.y;
+
+        // Where each foot should go, and how far the pelvis has to come down for the lowest one
+        let mut targets = Vec::new();
+        let mut pelvis_offset_wanted: f32 = 0.;
+        for bones in legs.iter() {
+            let foot_position: Vec3 = match world_affine(bones.foot, &transforms, &parent_query) {
+                Some(affine) => affine.translation.into(),
+                None => {
+                    targets.push(None);
+                    continue;
+                }
+            };
+            let lift = (foot_position.y - floor_height).max(0.); // How high the animation lifts the foot
+            let ray_origin = foot_position + Vec3::Y * IK_RAY_HEIGHT;
+            let hit = ray_cast(&collision_world.0, ray_origin, Vec3::NEG_Y, IK_RAY_HEIGHT + lift + IK_MAX_REACH);
+            if foot_ik.debug {
+                lines.line_colored(ray_origin, ray_origin - Vec3::Y * (IK_RAY_HEIGHT + lift + IK_MAX_REACH), 0., Color::GRAY);
+            }
+            if let Some(hit) = hit {
+                let target = hit.point + Vec3::Y * lift;
+                pelvis_offset_wanted = pelvis_offset_wanted.min(target.y - foot_position.y);
+                targets.push(Some((target, hit.normal)));
+            } else {
+                targets.push(None);
+            }
+        }
+
+        // Lower the pelvis, smoothed so it doesn't pop on steps
+        pelvis_offset_wanted = pelvis_offset_wanted.max(-foot_ik.max_pelvis_drop);
+        let smoothing_t = 1. - foot_ik.pelvis_smoothing.powf(dt);
+        foot_ik.pelvis_offset = foot_ik.pelvis_offset + (pelvis_offset_wanted - foot_ik.pelvis_offset) * smoothing_t;
+        let pelvis_parent = parent_query.get(pelvis).ok()
+            .and_then(|parent| world_affine(parent.get(), &transforms, &parent_query));
+        if let (Some(pelvis_parent), Ok(mut pelvis_transform)) = (pelvis_parent, transforms.get_mut(pelvis)) {
+            pelvis_transform.translation += pelvis_parent.inverse().transform_vector3(Vec3::Y * foot_ik.pelvis_offset);
+        }
+
+        // Bend each leg to its target and tilt the foot to the ground
+        let character_right = world_rotation(&character_transform) * Vec3::X;
+        for (bones, target) in legs.iter().zip(targets.iter()) {
+            let (target, normal) = match target {
+                Some(target) => *target,
+                None => continue,
+            };
+            let affines = (
+                world_affine(bones.hip, &transforms, &parent_query),
+                world_affine(bones.knee, &transforms, &parent_query),
+                world_affine(bones.foot, &transforms, &parent_query),
+            );
+            let (hip, knee, foot) = match affines {
+                (Some(hip), Some(knee), Some(foot)) => (hip, knee, foot),
+                _ => continue,
+            };
+            let foot_rotation = world_rotation(&foot);
+            let (hip_delta, knee_delta) = solve_two_bone(
+                hip.translation.into(), knee.translation.into(), foot.translation.into(), target, character_right);
+            rotate_bone_world(bones.hip, hip_delta, &mut transforms, &parent_query);
+            rotate_bone_world(bones.knee, knee_delta, &mut transforms, &parent_query);
+
+            // Keep the foot's animated orientation, tilted by the slope
+            let foot_wanted = Quat::from_rotation_arc(Vec3::Y, normal) * foot_rotation;
+            if let Some(foot_now) = world_affine(bones.foot, &transforms, &parent_query) {
+                rotate_bone_world(bones.foot, foot_wanted * world_rotation(&foot_now).inverse(), &mut transforms, &parent_query);
+            }
+
+            if foot_ik.debug {
+                let positions: Option<Vec<Vec3>> = [bones.hip, bones.knee, bones.foot].iter()
+                    .map(|bone| world_affine(*bone, &transforms, &parent_query).map(|affine| affine.translation.into()))
+                    .collect();
+                if let Some(positions) = positions {
+                    lines.line_gradient(positions[0], positions[1], 0., Color::YELLOW, Color::ORANGE);
+                    lines.line_gradient(positions[1], positions[2], 0., Color::ORANGE, Color::RED);
+                }
+                lines.line_colored(target - Vec3::X * 0.05, target + Vec3::X * 0.05, 0., Color::CYAN);
+                lines.line_colored(target - Vec3::Z * 0.05, target + Vec3::Z * 0.05, 0., Color::CYAN);
+                lines.line_colored(target, target + normal * 0.1, 0., Color::CYAN);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use serde::Deserialize;
+use crate::{
+    Camera,
+    animation::AnimationLink,
+    foot_ik::foot_ik_update,
+    pose::{find_named_descendant, world_affine, world_rotation, rotate_bone_world},
+};
+
+// Head and neck look-at, turns the head towards a point on top of the animated pose.
+// The turn is measured in character space as yaw and pitch from the character's forward (-Z),
+// limited, smoothed, and shared out over the bones by weight, neck first.
+
+pub struct LookAtPlugin;
+
+impl Plugin for LookAtPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<LookAtTarget>()
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            look_at_update
+                .after(foot_ik_update)
+                .before(TransformSystem::TransformPropagate));
+    }
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug)]
+pub enum LookAt {
+    Point(Vec3),
+    Entity(Entity),
+    CameraForward(f32), // A point this far ahead along the camera's view direction
+}
+impl Default for LookAt
+{
+    fn default() -> Self {
+        LookAt::CameraForward(10.)
+    }
+}
+
+#[derive(Reflect, FromReflect, Deserialize, Clone, Debug, Default)]
+pub struct LookAtBone {
+    pub name: String,
+    pub weight: f32, // Share of the turn, the weights of all bones usually add up to 1
+}
+
+#[derive(Reflect, Component, Deserialize, Clone, Debug)]
+#[reflect(Component)]
+#[serde(default)]
+pub struct LookAtTarget {
+    pub enabled: bool,
+    #[serde(skip)]
+    pub target: LookAt,
+    pub bones: Vec<LookAtBone>, // From the neck up, the last one's position is where we look from
+    pub max_yaw: f32,           // Radians either side of forward
+    pub max_pitch: f32,
+    pub smoothing: f32,         // Fraction of the turn still to go after a second
+    #[serde(skip)]
+    yaw: f32,
+    #[serde(skip)]
+    pitch: f32,
+}
+impl Default for LookAtTarget
+{
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target: LookAt::default(),
+            bones: Vec::new(),
+            max_yaw: 1.2,
+            max_pitch: 0.6,
+            smoothing: 0.01,
+            yaw: 0.,
+            pitch: 0.,
+        }
+    }
+}
+
+fn look_at_update(
+    time: Res<Time>,
+    mut characters_query: Query<(Entity, &mut LookAtTarget, &AnimationLink)>,
+    camera_query: Query<Entity, With<Camera>>,
+    global_transforms: Query<&GlobalTransform>,
+    children_query: Query<&Children>,
+    names_query: Query<&Name>,
+    parent_query: Query<&Parent>,
+    mut transforms: Query<&mut Transform>,
+){
+    let dt = time.delta_seconds();
+    for (character, mut look_at, anim_link) in characters_query.iter_mut() {
+        let bones: Option<Vec<(Entity, f32)>> = look_at.bones.iter()
+            .map(|bone| anim_link.players.values()
+                .find_map(|player| find_named_descendant(*player, &bone.name, &children_query, &names_query))
+                .map(|entity| (entity, bone.weight)))
+            .collect();
+        let bones = match bones {
+            Some(bones) if !bones.is_empty() => bones,
+            _ => continue, // Scene not spawned yet
+        };
+        let character_rotation = match world_affine(character, &transforms, &parent_query) {
+            Some(affine) => world_rotation(&affine),
+            None => continue,
+        };
+        let eye: Vec3 = match world_affine(bones.last().unwrap().0, &transforms, &parent_query) {
+            Some(affine) => affine.translation.into(),
+            None => continue,
+        };
+
+        let target = match look_at.target {
+            LookAt::Point(point) => Some(point),
+            LookAt::Entity(entity) => global_transforms.get(entity).ok().map(|transform| transform.translation()),
+            LookAt::CameraForward(distance) => camera_query.get_single().ok()
+                .and_then(|camera| global_transforms.get(camera).ok())
+                .map(|transform| transform.translation() + transform.forward() * distance),
+        };
+
+        // Wanted turn within the limits, back to forward when there's nothing to look at
+        let (yaw_wanted, pitch_wanted) = match target.filter(|_| look_at.enabled) {
+            Some(target) => {
+                let direction = character_rotation.inverse() * (target - eye);
+                let yaw = (-direction.x).atan2(-direction.z);
+                let pitch = direction.y.atan2(Vec2::new(direction.x, direction.z).length());
+                (yaw.clamp(-look_at.max_yaw, look_at.max_yaw), pitch.clamp(-look_at.max_pitch, look_at.max_pitch))
+            }
+            None => (0., 0.),
+        };
+        let smoothing_t = 1. - look_at.smoothing.powf(dt);
+        look_at.yaw += (yaw_wanted - look_at.yaw) * smoothing_t;
+        look_at.pitch += (pitch_wanted - look_at.pitch) * smoothing_t;
+
+        // Each bone adds its share of the turn, in world space.
+        // Rotating -Z about X by pitch tilts it up.
+        for (bone, weight) in bones {
+            let turn = Quat::from_rotation_y(look_at.yaw * weight) * Quat::from_rotation_x(look_at.pitch * weight);
+            rotate_bone_world(bone, character_rotation * turn * character_rotation.inverse(), &mut transforms, &parent_query);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use bevy::{prelude::*, render::{camera::Camera as ViewCamera, primitives::Aabb}};
+use bevy_prototype_debug_lines::*;
+use bevy_playground::{
+    collision::{Capsule, ray_cast},
+    mouse_look::MouseLookInput,
+    navigation::{NavMesh, NavMeshSettings},
+};
+use crate::{Player, Camera, SystemOrder,
+    character::CharacterCapsule,
+    controller::{CharacterController, CollisionWorld, StaticCollider, collision_world_update},
+};
+
+// Navigation for the characters, on a navmesh built from the collision world by
+// bevy_playground::navigation. It is rebuilt when the static colliders change.
+// Click on the floor and the player walks there along a path, with its usual movement,
+// any movement input takes back control. Paths are drawn with debug lines, N shows the navmesh.
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Navigation>()
+        .register_type::<NavAgent>()
+        .add_system(navmesh_update.after(collision_world_update))
+        .add_system(navigation_toggle)
+        .add_system(click_to_move.before(SystemOrder::PlayerMovement))
+        .add_system(navigation_debug_draw);
+    }
+}
+
+// Grid the navmesh is built on, smaller is closer to the walls but slower to build
+const NAV_CELL_SIZE: f32 = 0.25;
+// Lines are drawn this far above the ground so they don't disappear into it
+const NAV_LINE_HEIGHT: f32 = 0.05;
+
+#[derive(Resource, Default)]
+pub struct Navigation {
+    pub navmesh: NavMesh,
+    pub debug: bool, // Draw the navmesh polygons
+}
+
+// Follows a path, for whatever moves the character to steer along
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct NavAgent {
+    pub path: Vec<Vec3>,
+    pub next: usize,          // Waypoint heading for
+    pub waypoint_radius: f32, // Close enough to a waypoint to head for the next one
+}
+impl Default for NavAgent
+{
+    fn default() -> Self {
+        Self {
+            path: Vec::new(),
+            next: 0,
+            waypoint_radius: 0.3,
+        }
+    }
+}
+
+impl NavAgent {
+    pub fn set_path(&mut self, path: Vec<Vec3>) {
+        self.path = path;
+        self.next = 1; // The path starts where we are
+    }
+
+    pub fn clear(&mut self) {
+        self.path.clear();
+        self.next = 0;
+    }
+
+    // Direction to the next waypoint on the ground plane, None once the path is done
+    pub fn steer(&mut self, position: Vec3) -> Option<Vec3> {
+        while self.next < self.path.len() {
+            let offset = self.path[self.next] - position;
+            let offset = Vec3::new(offset.x, 0., offset.z);
+            if offset.length() > self.waypoint_radius {
+                return Some(offset.normalize());
+            }
+            self.next += 1;
+        }
+        self.clear();
+        None
+    }
+}
+
+// Rebuild when static colliders move or get their bounds, and when the player's capsule is known.
+// The navmesh is for the player's capsule and controller.
+fn navmesh_update(
+    mut navigation: ResMut<Navigation>,
+    collision_world: Res<CollisionWorld>,
+    changed_colliders_query: Query<(), (With<StaticCollider>, Or<(Changed<GlobalTransform>, Added<Aabb>)>)>,
+    added_agent_query: Query<(), (With<Player>, Added<CharacterCapsule>)>,
+    agent_query: Query<(&CharacterCapsule, &CharacterController), With<Player>>,
+){
+    if changed_colliders_query.is_empty() && added_agent_query.is_empty() {
+        return;
+    }
+    let (capsule, controller) = match agent_query.get_single() {
+        Ok(agent) => agent,
+        Err(_) => return,
+    };
+    let settings = NavMeshSettings {
+        cell_size: NAV_CELL_SIZE,
+        agent: Capsule {radius: capsule.radius, height: capsule.height},
+        max_slope: controller.max_slope,
+        step_height: controller.step_height,
+    };
+    navigation.navmesh = NavMesh::build(&collision_world.0, &settings);
+    info!("Navmesh built, {} polygons", navigation.navmesh.polygons.len());
+}
+
+fn navigation_toggle(
+    kb_input: Res<Input<KeyCode>>,
+    mut navigation: ResMut<Navigation>,
+){
+    if kb_input.just_pressed(KeyCode::N) {
+        navigation.debug = !navigation.debug;
+    }
+}
+
+// Left click on the ground walks the player there
+fn click_to_move(
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    look_input: Res<MouseLookInput>,
+    navigation: Res<Navigation>,
+    camera_query: Query<(&ViewCamera, &GlobalTransform), With<Camera>>,
+    collision_world: Res<CollisionWorld>,
+    mut player_query: Query<(&Transform, &mut NavAgent), With<Player>>,
+){
+    // No cursor to click with while mouse look has it
+    if !mouse_input.just_pressed(MouseButton::Left) || look_input.grabbed {
+        return;
+    }
+    let cursor = match windows.get_primary().and_then(|window| window.cursor_position()) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let ray = match camera_query.get_single() {
+        Ok((camera, camera_transform)) => camera.viewport_to_world(camera_transform, cursor),
+        Err(_) => return,
+    };
+    let hit = match ray.and_then(|ray| ray_cast(&collision_world.0, ray.origin, ray.direction.normalize(), 1000.)) {
+        Some(hit) => hit,
+        None => return,
+    };
+    if let Ok((transform, mut agent)) = player_query.get_single_mut() {
+        match navigation.navmesh.find_path(transform.translation, hit.point) {
+            Some(path) => agent.set_path(path),
+            None => {
+                agent.clear();
+                warn!("No path to {}", hit.point);
+            }
+        }
+    }
+}
+
+fn navigation_debug_draw(
+    navigation: Res<Navigation>,
+    mut lines: ResMut<DebugLines>,
+    agents_query: Query<(&Transform, &NavAgent)>,
+){
+    let lift = Vec3::Y * NAV_LINE_HEIGHT;
+    for (transform, agent) in agents_query.iter() {
+        let mut from = transform.translation;
+        for waypoint in agent.path.iter().skip(agent.next) {
+            lines.line_colored(from + lift, *waypoint + lift, 0., Color::CYAN);
+            from = *waypoint;
+        }
+    }
+
+    if navigation.debug {
+        for polygon in navigation.navmesh.polygons.iter() {
+            let corners = [
+                Vec3::new(polygon.min.x, polygon.height, polygon.min.y),
+                Vec3::new(polygon.max.x, polygon.height, polygon.min.y),
+                Vec3::new(polygon.max.x, polygon.height, polygon.max.y),
+                Vec3::new(polygon.min.x, polygon.height, polygon.max.y),
+            ];
+            for i in 0..4 {
+                lines.line_colored(corners[i] + lift, corners[(i + 1) % 4] + lift, 0., Color::MIDNIGHT_BLUE);
+            }
+            for link in polygon.links.iter() {
+                lines.line_colored(link.portal.0 + lift, link.portal.1 + lift, 0., Color::GREEN);
+            }
+        }
+    }
+}
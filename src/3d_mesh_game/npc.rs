@@ -0,0 +1,149 @@
+use std::f32::consts::TAU;
+use bevy::prelude::*;
+use rand::{thread_rng, Rng};
+use crate::{Player, Speed,
+    animation_state::AnimationStateMachine,
+    behaviour_tree::{BehaviourAction, BehaviourTreeRunner, Blackboard, Status, behaviour_tree_update},
+    character::CharacterBundle,
+    controller::CharacterController,
+    look_at::{LookAt, LookAtTarget},
+    steering::{Steering, SteeringBehaviour, SteeringTarget, WeightedBehaviour},
+};
+
+// Fox NPCs around the playground. They are characters like the player, steered instead of controlled,
+// so they animate with the same graph and walk with the same controller.
+// What they do is up to their behaviour tree: the tree picks an action, npc_actions turns it
+// into steering behaviours, and npc_senses tells the tree where the player is.
+
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin{
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(npc_spawn)
+        .add_system(npc_character_setup)
+        .add_system(npc_senses.before(behaviour_tree_update))
+        .add_system(npc_actions.after(behaviour_tree_update));
+    }
+}
+
+const NPC_CHARACTER_PATH: &str = "models/Fox.character.ron";
+const NPC_BEHAVIOUR_PATH: &str = "ai/fox.bt.ron";
+const NPC_COUNT: usize = 24;
+// Spawned in a ring around the origin, outside of the obstacles
+const NPC_SPAWN_DISTANCE_MIN: f32 = 13.;
+const NPC_SPAWN_DISTANCE_MAX: f32 = 30.;
+// Following stops this far from the player, outside of the fox tree's flee distance, or it would run off again
+const FOLLOW_DISTANCE: f32 = 2.5;
+
+#[derive(Component, Default)]
+pub struct Npc {
+    action_time: f32,
+    action_duration: Option<f32>, // Actions without one run until the tree moves on
+}
+
+fn npc_spawn(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let mut rng = thread_rng();
+    let definition = asset_server.load(NPC_CHARACTER_PATH);
+    let behaviour = asset_server.load(NPC_BEHAVIOUR_PATH);
+    for i in 0..NPC_COUNT {
+        let angle = i as f32 / NPC_COUNT as f32 * TAU;
+        let distance = rng.gen_range(NPC_SPAWN_DISTANCE_MIN..NPC_SPAWN_DISTANCE_MAX);
+        let transform = Transform::from_xyz(angle.cos() * distance, 0., angle.sin() * distance)
+            .with_rotation(Quat::from_rotation_y(rng.gen_range(0f32..TAU)));
+
+        commands.spawn((
+            CharacterBundle::new(definition.clone(), transform),
+            Name::new(format!("Npc {}", i))
+        ))
+        .insert(Speed::default())
+        .insert(CharacterController::default())
+        .insert(Steering {
+            max_speed: 2.5,
+            behaviours: action_behaviours("", transform.translation, None),
+            ..default()
+        })
+        .insert(BehaviourTreeRunner::new(behaviour.clone()))
+        .insert(Blackboard::default())
+        .insert(BehaviourAction::default())
+        .insert(Npc::default());
+    }
+}
+
+// What the tree needs to know: the player and how far away it is
+fn npc_senses(
+    player_query: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut npcs_query: Query<(&GlobalTransform, &mut Blackboard), With<Npc>>,
+){
+    let player = player_query.get_single().ok();
+    for (transform, mut blackboard) in npcs_query.iter_mut() {
+        blackboard.set_entity("player", player.map(|(entity, _)| entity));
+        let distance = player.map_or(f32::MAX, |(_, player_transform)| {
+            (player_transform.translation() - transform.translation()).length()
+        });
+        blackboard.set("player_distance", distance);
+    }
+}
+
+// Steering behaviours for an action, on top of keeping apart and out of walls.
+// Unknown actions and no action just do that.
+fn action_behaviours(action: &str, position: Vec3, player: Option<Entity>) -> Vec<WeightedBehaviour> {
+    let mut behaviours = vec![
+        WeightedBehaviour::new(SteeringBehaviour::Separation {radius: 1.5}, 2.),
+        WeightedBehaviour::new(SteeringBehaviour::ObstacleAvoidance {look_ahead: 2.}, 3.),
+    ];
+    let player = player.map(SteeringTarget::Entity);
+    match (action, player) {
+        // Come to a stop where we are
+        ("idle", _) => behaviours.push(WeightedBehaviour::new(
+            SteeringBehaviour::Arrive {target: SteeringTarget::Point(position), slowing_distance: 1., stand_off: 0.}, 1.)),
+        ("wander", _) => behaviours.push(WeightedBehaviour::new(
+            SteeringBehaviour::Wander {distance: 2., radius: 1., jitter: 4.}, 1.)),
+        ("follow", Some(player)) => behaviours.push(WeightedBehaviour::new(
+            SteeringBehaviour::Arrive {target: player, slowing_distance: 4., stand_off: FOLLOW_DISTANCE}, 1.)),
+        ("flee", Some(player)) => behaviours.push(WeightedBehaviour::new(
+            SteeringBehaviour::Flee {target: player, panic_distance: 3.}, 2.)),
+        _ => (),
+    }
+    behaviours
+}
+
+// Start the actions the trees ask for, and finish the ones that run for a while
+fn npc_actions(
+    time: Res<Time>,
+    mut npcs_query: Query<(&mut Npc, &mut Steering, &Transform, &Blackboard, &mut BehaviourAction)>,
+){
+    let mut rng = thread_rng();
+    for (mut npc, mut steering, transform, blackboard, mut action) in npcs_query.iter_mut() {
+        if action.is_changed() && action.status == Status::Running {
+            steering.behaviours = action_behaviours(&action.name, transform.translation, blackboard.entity("player"));
+            npc.action_time = 0.;
+            npc.action_duration = match action.name.as_str() {
+                "idle" => Some(rng.gen_range(2f32..5.)),
+                "wander" => Some(rng.gen_range(4f32..10.)),
+                _ => None,
+            };
+        }
+
+        npc.action_time += time.delta_seconds();
+        if action.status == Status::Running && npc.action_duration.map_or(false, |duration| npc.action_time > duration) {
+            action.status = Status::Success;
+        }
+    }
+}
+
+// Once the NPCs' characters are set up, look at the player
+fn npc_character_setup(
+    player_query: Query<Entity, With<Player>>,
+    mut npcs_query: Query<&mut LookAtTarget, (With<Npc>, Added<AnimationStateMachine>)>,
+){
+    let player = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for mut look_at in npcs_query.iter_mut() {
+        look_at.target = LookAt::Entity(player);
+    }
+}
@@ -1,28 +1,34 @@
-use bevy::{prelude::*};
+use bevy::prelude::*;
 use bevy_prototype_debug_lines::*;
-use const_format::concatcp;
-use crate::{Player, animation::AnimationLink, Camera, CameraRotation, Speed, MyCustomMaterial, SystemOrder,
+use bevy_playground::{
+    camera_rig::{CameraMode, CameraRig, CameraTarget},
+    camera_shake::CameraTrauma,
+};
+use crate::{Player, Camera, Speed, MyCustomMaterial, SystemOrder,
+    animation_state::{AnimationStateMachine, AnimationEvent},
+    character::CharacterBundle,
+    root_motion::RootMotion,
+    controller::{CharacterController, CharacterImpact, ImpactKind},
+    navigation::NavAgent,
+    skeleton_debug::SkeletonDebug,
     GAMEPAD_DEADZONE, GAMEPAD_AXIS_L_SENSITIVITY};
 
-const PLAYER_MESH_PATH: &str = "models/Fox.glb";
+const PLAYER_CHARACTER_PATH: &str = "models/Fox.character.ron";
+// Camera trauma per m/s of speed lost when the player lands or runs into something
+const LANDING_TRAUMA: f32 = 0.08;
+const COLLISION_TRAUMA: f32 = 0.1;
 
 pub struct PlayerPlugin;
 
-#[derive(Resource)]
-struct PlayerAnimations
-{
-    walk: Handle<AnimationClip>,
-    idle: Handle<AnimationClip>,
-    run:  Handle<AnimationClip>,
-}
-
 impl Plugin for PlayerPlugin{
     fn build(&self, app: &mut App) {
         app.add_startup_stage(
             "setup_player",
             SystemStage::single(player_spawn))
         .add_system(player_movement.label(SystemOrder::PlayerMovement))
-        .add_system(player_animation);
+        .add_system(player_animation)
+        .add_system(player_footstep_markers)
+        .add_system(player_impact_shake);
     }
 }
 
@@ -32,28 +38,18 @@ fn player_spawn(
     // mut materials: ResMut<Assets<MyCustomMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
-    // Make a player sphere
+    // Make a player character, the model, scale and animations come from its sidecar
     commands.spawn((
-        SceneBundle {
-            scene: asset_server.load(concatcp!(PLAYER_MESH_PATH, "#Scene0")),
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.0),
-                scale: Vec3::new(0.01, 0.01, 0.01),
-                ..default()
-            },
-            ..default()
-        },
+        CharacterBundle::new(asset_server.load(PLAYER_CHARACTER_PATH), Transform::from_xyz(0.0, 0.0, 0.0)),
         Name::new("Player")
     ))
     // Custom components
     .insert(Speed::default())
+    .insert(CameraTarget::default())
+    .insert(CharacterController::default())
+    .insert(NavAgent::default())
+    .insert(SkeletonDebug::default())
     .insert(Player);
-
-    commands.insert_resource(PlayerAnimations {
-        walk: asset_server.load(concatcp!(PLAYER_MESH_PATH, "#Animation1")),
-        idle: asset_server.load(concatcp!(PLAYER_MESH_PATH, "#Animation0")),
-        run:  asset_server.load(concatcp!(PLAYER_MESH_PATH, "#Animation2")),
-    });
 }
 
 
@@ -63,10 +59,10 @@ fn player_movement(
     gamepads: Res<Gamepads>,
     gamepad_axes: Res<Axis<GamepadAxis>>,
     mut lines: ResMut<DebugLines>,
-    mut player_query: Query<(&mut Speed, &mut Transform), (With<Player>, Without<Camera>)>,
-    camera_query: Query<&CameraRotation, (With<Camera>, Without<Player>)>
+    mut player_query: Query<(&mut Speed, &mut Transform, Option<&mut RootMotion>, Option<&mut NavAgent>), (With<Player>, Without<Camera>)>,
+    camera_query: Query<&CameraRig, (With<Camera>, Without<Player>)>
 ){  
-    if let Ok((mut speed, mut transform)) = player_query.get_single_mut() {
+    if let Ok((mut speed, mut transform, mut root_motion, nav_agent)) = player_query.get_single_mut() {
         let dt = time.delta_seconds();
         // Construct input vector from keyboard presses
         let mut move_input = Vec3::new(
@@ -85,9 +81,21 @@ fn player_movement(
             }
         }
 
-        // Transform input to world space
-        if let Ok(angle) = camera_query.get_single() {
-            move_input = Quat::from_rotation_y(angle.0.y) * move_input;
+        // Transform input to world space, a free fly camera has the movement keys to itself
+        if let Ok(rig) = camera_query.get_single() {
+            if rig.mode == CameraMode::FreeFly {
+                move_input = Vec3::ZERO;
+            }
+            move_input = Quat::from_rotation_y(rig.yaw) * move_input;
+        }
+
+        // Walk a click-to-move path while there's no input, any input takes over
+        if let Some(mut nav_agent) = nav_agent {
+            if move_input.length_squared() > 0. {
+                nav_agent.clear();
+            } else if let Some(direction) = nav_agent.steer(transform.translation) {
+                move_input = direction;
+            }
         }
 
         // Accelerate
@@ -101,12 +109,18 @@ fn player_movement(
         let speed_dir = speed.0.normalize_or_zero();            // Can still have magnitude when move_dir = 0
         speed.0 = speed_dir * speed_magnitude;
 
-        // Update position with velocity (arc=a*r)
-        transform.translation += speed.0 * dt;
+        // Toggle root motion, when on the clips move the player and speed only drives the animation graph
+        if kb_input.just_pressed(KeyCode::R) {
+            if let Some(root_motion) = root_motion.as_mut() {
+                root_motion.enabled = !root_motion.enabled;
+            }
+        }
+
+        // The character controller moves us with the speed (arc=a*r), colliding with the level
         // Point player in velocity direction, with a little inertia
         if speed_magnitude > 0.01 {
             transform.rotation = transform.rotation.slerp(
-                transform.looking_at(transform.translation - speed_dir, Vec3::new(0., 1., 0.)).rotation, 
+                transform.looking_at(transform.translation + speed_dir, Vec3::new(0., 1., 0.)).rotation, 
                 (dt * 5.).min(1.));   
         }
 
@@ -122,30 +136,60 @@ fn player_movement(
 }
 
 
+// Fade the look around layer in and out with L, the locomotion is animated by character_locomotion
 fn player_animation(
-    animations: Res<PlayerAnimations>,
-    mut anim_players_query: Query<&mut AnimationPlayer>,
-    query: Query<(&Speed, &AnimationLink), With<Player>>,
+    time: Res<Time>,
+    kb_input: Res<Input<KeyCode>>,
+    mut look_around: Local<bool>,
+    mut query: Query<&mut AnimationStateMachine, With<Player>>,
 ){
-    for (speed, anim_link) in query.iter() {
-        if let Ok(mut anim) = anim_players_query.get_mut(anim_link.0) {
-            // Switch anim states and playback speed based on entity speed.
-            // Lots of magic numbers below.
-            let speed = speed.0.length();
-            let walk_speed = 0.01;
-            let run_speed = 1.5;
-            if speed < walk_speed {
-                anim.play(animations.idle.clone_weak()).repeat();
-                anim.set_speed(1.);
-            }
-            else if speed < run_speed {
-                anim.play(animations.walk.clone_weak()).repeat();
-                anim.set_speed(0.1 + 0.6 * ((speed - walk_speed) / run_speed));
-            }
-            else {
-                anim.play(animations.run.clone_weak()).repeat();
-                anim.set_speed(1. + 4. * ((speed - run_speed) / 2.));
-            }
+    if kb_input.just_pressed(KeyCode::L) {
+        *look_around = !*look_around;
+    }
+    for mut state_machine in query.iter_mut() {
+        // Over a third of a second
+        if let Some(layer) = state_machine.layer_mut("look_around") {
+            let target = if *look_around {1.} else {0.};
+            let step = time.delta_seconds() * 3.;
+            layer.weight = (layer.weight + (target - layer.weight).clamp(-step, step)).clamp(0., 1.);
+        }
+    }
+}
+
+
+// Mark footsteps with a short line on the floor
+fn player_footstep_markers(
+    mut events: EventReader<AnimationEvent>,
+    mut lines: ResMut<DebugLines>,
+    player_query: Query<&GlobalTransform, With<Player>>,
+){
+    for event in events.iter() {
+        if let Ok(transform) = player_query.get(event.entity) {
+            let side = match event.name.as_str() {
+                "footstep_left" => -1.,
+                "footstep_right" => 1.,
+                _ => continue,
+            };
+            let (_, rotation, translation) = transform.to_scale_rotation_translation();
+            let position = translation + rotation * Vec3::new(side * 0.1, 0., 0.);
+            lines.line_colored(position, position + Vec3::Y * 0.3, 0.5, Color::WHITE);
         }
     }
-}
\ No newline at end of file
+}
+
+// Shake the camera when the player lands hard or runs into things
+fn player_impact_shake(
+    mut impact_events: EventReader<CharacterImpact>,
+    mut trauma_events: EventWriter<CameraTrauma>,
+    player_query: Query<(), With<Player>>,
+){
+    for impact in impact_events.iter() {
+        if player_query.contains(impact.entity) {
+            let trauma_per_speed = match impact.kind {
+                ImpactKind::Landed => LANDING_TRAUMA,
+                ImpactKind::Collided => COLLISION_TRAUMA,
+            };
+            trauma_events.send(CameraTrauma::new(impact.speed * trauma_per_speed));
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use bevy::{math::Affine3A, prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+// Pose sampling and blending.
+// Bevy 0.9's AnimationPlayer can only play a single clip at a time, so to blend between clips
+// we sample the clips ourselves into poses, blend the poses and write the result to the bones.
+
+// Local transform of one bone. Channels that aren't animated are None,
+// so they can be left alone or blended only with the poses that do animate them.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BonePose {
+    pub translation: Option<Vec3>,
+    pub rotation: Option<Quat>,
+    pub scale: Option<Vec3>,
+}
+
+// A full pose, keyed on bone path just like the curves in an AnimationClip
+#[derive(Clone, Default, Debug)]
+pub struct Pose(pub HashMap<EntityPath, BonePose>);
+
+// Wrap time into the clip for looping playback
+pub fn wrap_time(time: f32, duration: f32) -> f32 {
+    if duration > 0. {time.rem_euclid(duration)} else {0.}
+}
+
+// Find the keyframe pair around time and the interpolation factor between them.
+// Times outside the keyframes clamp to the first or last keyframe.
+fn keyframe_span(timestamps: &[f32], time: f32) -> (usize, usize, f32) {
+    let last = timestamps.len() - 1;
+    if last == 0 || time <= timestamps[0] {
+        return (0, 0, 0.);
+    }
+    if time >= timestamps[last] {
+        return (last, last, 0.);
+    }
+    let end = timestamps.partition_point(|t| *t <= time);
+    let start = end - 1;
+    let t = (time - timestamps[start]) / (timestamps[end] - timestamps[start]);
+    (start, end, t)
+}
+
+pub fn sample_curve(curve: &VariableCurve, time: f32, bone: &mut BonePose) {
+    if curve.keyframe_timestamps.is_empty() {
+        return;
+    }
+    let (start, end, t) = keyframe_span(&curve.keyframe_timestamps, time);
+    match &curve.keyframes {
+        Keyframes::Rotation(keyframes) => {
+            let rot_start = keyframes[start].normalize();
+            let mut rot_end = keyframes[end].normalize();
+            // Choose the smallest angle for the rotation
+            if rot_end.dot(rot_start) < 0. {
+                rot_end = -rot_end;
+            }
+            bone.rotation = Some(rot_start.slerp(rot_end, t));
+        }
+        Keyframes::Translation(keyframes) => {
+            bone.translation = Some(keyframes[start].lerp(keyframes[end], t));
+        }
+        Keyframes::Scale(keyframes) => {
+            bone.scale = Some(keyframes[start].lerp(keyframes[end], t));
+        }
+    }
+}
+
+// Sample all curves of a clip at time (not wrapped, use wrap_time for looping)
+pub fn sample_clip(clip: &AnimationClip, time: f32) -> Pose {
+    let mut pose = Pose::default();
+    for (path, curves) in clip.curves() {
+        let bone = pose.0.entry(path.clone()).or_default();
+        for curve in curves {
+            sample_curve(curve, time, bone);
+        }
+    }
+    pose
+}
+
+// Weighted sums of the bone channels, normalized by the weight that actually touched each channel
+#[derive(Default)]
+struct BoneAccumulator {
+    translation: Vec3,
+    translation_weight: f32,
+    rotation: Quat,
+    rotation_weight: f32,
+    scale: Vec3,
+    scale_weight: f32,
+}
+
+// Blends any number of weighted poses together
+#[derive(Default)]
+pub struct PoseBlender {
+    bones: HashMap<EntityPath, BoneAccumulator>,
+}
+
+impl PoseBlender {
+    pub fn add(&mut self, pose: &Pose, weight: f32) {
+        if weight <= 0. {
+            return;
+        }
+        for (path, bone) in pose.0.iter() {
+            let accumulator = self.bones.entry(path.clone()).or_insert_with(|| BoneAccumulator {
+                rotation: Quat::from_xyzw(0., 0., 0., 0.),
+                ..default()
+            });
+            if let Some(translation) = bone.translation {
+                accumulator.translation += translation * weight;
+                accumulator.translation_weight += weight;
+            }
+            if let Some(rotation) = bone.rotation {
+                // Normalized lerp, keep all rotations in the same hemisphere as the first one
+                let rotation = if accumulator.rotation.dot(rotation) < 0. {-rotation} else {rotation};
+                accumulator.rotation = accumulator.rotation + rotation * weight;
+                accumulator.rotation_weight += weight;
+            }
+            if let Some(scale) = bone.scale {
+                accumulator.scale += scale * weight;
+                accumulator.scale_weight += weight;
+            }
+        }
+    }
+
+    pub fn finish(self) -> Pose {
+        let mut pose = Pose::default();
+        for (path, accumulator) in self.bones {
+            pose.0.insert(path, BonePose {
+                translation: (accumulator.translation_weight > 0.).then(|| accumulator.translation / accumulator.translation_weight),
+                rotation: (accumulator.rotation_weight > 0.).then(|| accumulator.rotation.normalize()),
+                scale: (accumulator.scale_weight > 0.).then(|| accumulator.scale / accumulator.scale_weight),
+            });
+        }
+        pose
+    }
+}
+
+// Which bones a layer affects: the bones named in include and everything below them,
+// minus the bones named in exclude and everything below those. An empty include has all bones.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct BoneMask {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl BoneMask {
+    pub fn contains(&self, path: &EntityPath) -> bool {
+        let below = |bones: &[String]| path.parts.iter().any(|part| bones.iter().any(|bone| part.as_str() == bone));
+        (self.include.is_empty() || below(&self.include)) && !below(&self.exclude)
+    }
+}
+
+// Blend a layer over the pose, replacing it by weight on the masked bones.
+// Channels the pose doesn't animate are blended from the rest pose, or identity without one.
+pub fn blend_override(pose: &mut Pose, layer: &Pose, rest: &Pose, weight: f32, mask: &BoneMask) {
+    for (path, layer_bone) in layer.0.iter().filter(|(path, _)| mask.contains(path)) {
+        let rest_bone = rest.0.get(path).copied().unwrap_or_default();
+        let bone = pose.0.entry(path.clone()).or_default();
+        if let Some(translation) = layer_bone.translation {
+            let base = bone.translation.or(rest_bone.translation).unwrap_or(Vec3::ZERO);
+            bone.translation = Some(base.lerp(translation, weight));
+        }
+        if let Some(rotation) = layer_bone.rotation {
+            let base = bone.rotation.or(rest_bone.rotation).unwrap_or(Quat::IDENTITY);
+            bone.rotation = Some(base.slerp(rotation, weight));
+        }
+        if let Some(scale) = layer_bone.scale {
+            let base = bone.scale.or(rest_bone.scale).unwrap_or(Vec3::ONE);
+            bone.scale = Some(base.lerp(scale, weight));
+        }
+    }
+}
+
+// Add a layer's difference from its reference pose onto the pose by weight, on the masked bones
+pub fn blend_additive(pose: &mut Pose, layer: &Pose, reference: &Pose, weight: f32, mask: &BoneMask) {
+    for (path, layer_bone) in layer.0.iter().filter(|(path, _)| mask.contains(path)) {
+        let reference_bone = reference.0.get(path).copied().unwrap_or_default();
+        let bone = match pose.0.get_mut(path) {
+            Some(bone) => bone,
+            None => continue, // Nothing to add to
+        };
+        if let (Some(base), Some(translation), Some(reference)) = (bone.translation, layer_bone.translation, reference_bone.translation) {
+            bone.translation = Some(base + (translation - reference) * weight);
+        }
+        if let (Some(base), Some(rotation), Some(reference)) = (bone.rotation, layer_bone.rotation, reference_bone.rotation) {
+            let delta = Quat::IDENTITY.slerp(reference.inverse() * rotation, weight);
+            bone.rotation = Some((base * delta).normalize());
+        }
+        if let (Some(base), Some(scale), Some(reference)) = (bone.scale, layer_bone.scale, reference_bone.scale) {
+            bone.scale = Some(base * Vec3::ONE.lerp(scale / reference, weight));
+        }
+    }
+}
+
+// Find the bone entity for a path below the animation root entity.
+// Same lookup as Bevy's animation player: the first name is the root itself.
+pub fn find_bone(
+    root: Entity,
+    path: &EntityPath,
+    children_query: &Query<&Children>,
+    names_query: &Query<&Name>,
+) -> Option<Entity> {
+    let mut current_entity = root;
+    for part in path.parts.iter().skip(1) {
+        let children = children_query.get(current_entity).ok()?;
+        current_entity = *children.iter().find(|child| {
+            names_query.get(**child).map_or(false, |name| name == part)
+        })?;
+    }
+    Some(current_entity)
+}
+
+// Find a bone by name anywhere below root
+pub fn find_named_descendant(root: Entity, name: &str, children_query: &Query<&Children>, names_query: &Query<&Name>) -> Option<Entity> {
+    for child in children_query.get(root).ok()?.iter() {
+        if names_query.get(*child).map_or(false, |child_name| child_name.as_str() == name) {
+            return Some(*child);
+        }
+        if let Some(found) = find_named_descendant(*child, name, children_query, names_query) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+// Write a pose to the bones below root. Found bone entities are cached in bones.
+pub fn apply_pose(
+    pose: &Pose,
+    root: Entity,
+    bones: &mut HashMap<EntityPath, Entity>,
+    children_query: &Query<&Children>,
+    names_query: &Query<&Name>,
+    transforms: &mut Query<&mut Transform>,
+) {
+    for (path, bone) in pose.0.iter() {
+        let bone_entity = match bones.get(path) {
+            Some(entity) => *entity,
+            None => match find_bone(root, path, children_query, names_query) {
+                Some(entity) => {
+                    bones.insert(path.clone(), entity);
+                    entity
+                }
+                None => continue,
+            },
+        };
+        if let Ok(mut transform) = transforms.get_mut(bone_entity) {
+            if let Some(translation) = bone.translation {
+                transform.translation = translation;
+            }
+            if let Some(rotation) = bone.rotation {
+                transform.rotation = rotation;
+            }
+            if let Some(scale) = bone.scale {
+                transform.scale = scale;
+            }
+        }
+    }
+}
+
+// World transform of an entity from the local transforms, the global ones are a frame old here
+pub fn world_affine(entity: Entity, transforms: &Query<&mut Transform>, parent_query: &Query<&Parent>) -> Option<Affine3A> {
+    let mut affine = transforms.get(entity).ok()?.compute_affine();
+    let mut current = entity;
+    while let Ok(parent) = parent_query.get(current) {
+        current = parent.get();
+        affine = transforms.get(current).ok()?.compute_affine() * affine;
+    }
+    Some(affine)
+}
+
+pub fn world_rotation(affine: &Affine3A) -> Quat {
+    affine.to_scale_rotation_translation().1
+}
+
+// Rotate a bone by a world space rotation, keeping its parent as is
+pub fn rotate_bone_world(bone: Entity, world_rotation_delta: Quat, transforms: &mut Query<&mut Transform>, parent_query: &Query<&Parent>) {
+    let parent_rotation = parent_query.get(bone).ok()
+        .and_then(|parent| world_affine(parent.get(), transforms, parent_query))
+        .map_or(Quat::IDENTITY, |affine| world_rotation(&affine));
+    if let Ok(mut transform) = transforms.get_mut(bone) {
+        // local' = parent^-1 * delta * parent * local
+        transform.rotation = (parent_rotation.inverse() * world_rotation_delta * parent_rotation * transform.rotation).normalize();
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::{math::Affine3A, prelude::*, transform::TransformSystem};
+use crate::{
+    animation_state::animation_state_machine_update,
+    controller::CharacterController,
+    pose::{BonePose, sample_curve},
+};
+
+// Root motion, let the clips move the character instead of tuning constants.
+// The state machine measures how far the root bone moved in the playing clips this frame,
+// keeps the bone itself in place and stores the motion here. root_motion_apply then moves
+// the character's Transform by it, or only turns it if a CharacterController does the moving.
+
+pub struct RootMotionPlugin;
+
+impl Plugin for RootMotionPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<RootMotion>()
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            root_motion_apply
+                .after(animation_state_machine_update)
+                .before(TransformSystem::TransformPropagate));
+    }
+}
+
+#[derive(Reflect, Component, Default)]
+#[reflect(Component)]
+pub struct RootMotion {
+    pub enabled: bool,     // When disabled the clips play as authored and game code moves the character
+    pub bone: String,      // Name of the bone that carries the motion, usually the hips
+    pub translation: Vec3, // This frame's motion in character space, horizontal only
+    pub rotation: Quat,    // This frame's turn around the character's up axis
+}
+
+impl RootMotion {
+    pub fn new(bone: &str) -> Self {
+        Self {
+            enabled: false,
+            bone: bone.to_string(),
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+        }
+    }
+}
+
+// Path of the root bone in a clip, by bone name
+pub fn find_bone_path<'a>(clip: &'a AnimationClip, bone: &str) -> Option<&'a EntityPath> {
+    clip.curves().keys().find(|path| path.parts.last().map_or(false, |name| name.as_str() == bone))
+}
+
+fn sample_bone(clip: &AnimationClip, path: &EntityPath, time: f32) -> (Vec3, Quat) {
+    let mut bone = BonePose::default();
+    for curve in clip.curves().get(path).into_iter().flatten() {
+        sample_curve(curve, time, &mut bone);
+    }
+    (bone.translation.unwrap_or(Vec3::ZERO), bone.rotation.unwrap_or(Quat::IDENTITY))
+}
+
+// How the root bone moved from previous to current clip time, in the bone's parent space.
+// loops is how many times the clip wrapped around in between, each adds one full cycle of motion.
+pub fn clip_root_motion(clip: &AnimationClip, path: &EntityPath, previous: f32, current: f32, loops: f32) -> (Vec3, Quat) {
+    let (start_translation, start_rotation) = sample_bone(clip, path, 0.);
+    let (end_translation, end_rotation) = sample_bone(clip, path, clip.duration());
+    let (previous_translation, previous_rotation) = sample_bone(clip, path, previous);
+    let (current_translation, current_rotation) = sample_bone(clip, path, current);
+
+    let translation = current_translation - previous_translation + (end_translation - start_translation) * loops;
+    let cycle_rotation = end_rotation * start_rotation.inverse();
+    let mut rotation = current_rotation * previous_rotation.inverse();
+    for _ in 0..loops.abs() as u32 {
+        rotation = if loops > 0. {cycle_rotation * rotation} else {cycle_rotation.inverse() * rotation};
+    }
+    (translation, rotation)
+}
+
+// Turn around the Y axis only, the twist part of a rotation
+pub fn yaw_of(rotation: Quat) -> Quat {
+    let twist = Quat::from_xyzw(0., rotation.y, 0., rotation.w);
+    if twist.length_squared() > 0. {twist.normalize()} else {Quat::IDENTITY}
+}
+
+// Convert motion of a bone from its parent's space to character space.
+// parent_to_character maps the bone's parent space into the character's local space.
+pub fn to_character_space(parent_to_character: &Affine3A, translation: Vec3, rotation: Quat) -> (Vec3, Quat) {
+    let (_, parent_rotation, _) = parent_to_character.to_scale_rotation_translation();
+    let translation = parent_to_character.transform_vector3(translation);
+    let rotation = parent_rotation * rotation * parent_rotation.inverse();
+    (Vec3::new(translation.x, 0., translation.z), yaw_of(rotation))
+}
+
+// Where the root bone is at the start of a clip
+pub fn clip_root_start(clip: &AnimationClip, path: &EntityPath) -> (Vec3, Quat) {
+    sample_bone(clip, path, 0.)
+}
+
+// Keep the root bone in place: drop its horizontal travel and turn relative to the reference
+// (the blended clip starts), keep the vertical bob and the tilts.
+pub fn pin_root_bone(bone: &mut BonePose, reference: (Vec3, Quat), parent_to_character: &Affine3A) {
+    let (reference_translation, reference_rotation) = reference;
+    let (_, parent_rotation, _) = parent_to_character.to_scale_rotation_translation();
+    if let Some(translation) = bone.translation.as_mut() {
+        let offset = parent_to_character.transform_vector3(*translation - reference_translation);
+        *translation = reference_translation + parent_to_character.inverse().transform_vector3(Vec3::Y * offset.y);
+    }
+    if let Some(rotation) = bone.rotation.as_mut() {
+        let turn = yaw_of(parent_rotation * *rotation * reference_rotation.inverse() * parent_rotation.inverse());
+        *rotation = (parent_rotation.inverse() * turn.inverse() * parent_rotation * *rotation).normalize();
+    }
+}
+
+// Move the characters by the motion of their clips
+pub fn root_motion_apply(
+    mut query: Query<(&RootMotion, &mut Transform, Option<&CharacterController>)>,
+){
+    for (root_motion, mut transform, controller) in query.iter_mut() {
+        if root_motion.enabled {
+            if controller.is_none() {
+                let translation = transform.rotation * (transform.scale * root_motion.translation);
+                transform.translation += translation;
+            }
+            transform.rotation = transform.rotation * root_motion.rotation;
+        }
+    }
+}
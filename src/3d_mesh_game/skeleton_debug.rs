@@ -0,0 +1,147 @@
+use bevy::{prelude::*, render::mesh::skinning::SkinnedMesh, transform::TransformSystem, utils::HashSet};
+use bevy_prototype_debug_lines::*;
+
+// Skeleton debug overlay. Draws the joints of the character's skinned meshes as lines
+// from their parent joints, with their local axes (X red, Y green, Z blue).
+// The joint names are listed in the inspector. Toggle with K.
+
+pub struct SkeletonDebugPlugin;
+
+impl Plugin for SkeletonDebugPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<SkeletonDebug>()
+        .add_system(skeleton_debug_toggle)
+        // After propagation so we draw this frame's final pose, IK and all
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            skeleton_debug_draw.after(TransformSystem::TransformPropagate));
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Joint {
+    entity: Entity,
+    parent: Option<Entity>, // Parent joint
+    depth: usize,
+}
+
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct SkeletonDebug {
+    pub enabled: bool,
+    pub axis_length: f32, // World units, so the axes stay readable whatever the import scale
+    pub joints: Vec<String>,
+    #[reflect(ignore)]
+    joint_entities: Vec<Joint>, // Found again when the hierarchy changes
+}
+impl Default for SkeletonDebug
+{
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            axis_length: 0.05,
+            joints: Vec::new(),
+            joint_entities: Vec::new(),
+        }
+    }
+}
+
+fn skeleton_debug_toggle(
+    kb_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut SkeletonDebug>,
+){
+    if kb_input.just_pressed(KeyCode::K) {
+        for mut skeleton_debug in query.iter_mut() {
+            skeleton_debug.enabled = !skeleton_debug.enabled;
+        }
+    }
+}
+
+// Add a joint and the joints below it, parents first
+fn add_joint(
+    joint: Entity,
+    parent: Option<Entity>,
+    depth: usize,
+    skin_joints: &HashSet<Entity>,
+    children_query: &Query<&Children>,
+    joints: &mut Vec<Joint>,
+) {
+    joints.push(Joint {entity: joint, parent, depth});
+    for child in children_query.get(joint).into_iter().flat_map(|children| children.iter()) {
+        if skin_joints.contains(child) {
+            add_joint(*child, Some(joint), depth + 1, skin_joints, children_query, joints);
+        }
+    }
+}
+
+// The joints of the skinned meshes below the character
+fn find_joints(
+    character: Entity,
+    children_query: &Query<&Children>,
+    parent_query: &Query<&Parent>,
+    skinned_query: &Query<&SkinnedMesh>,
+) -> Vec<Joint> {
+    let mut skin_joints = HashSet::default();
+    let mut stack = vec![character];
+    while let Some(entity) = stack.pop() {
+        if let Ok(skinned_mesh) = skinned_query.get(entity) {
+            skin_joints.extend(skinned_mesh.joints.iter().copied());
+        }
+        stack.extend(children_query.get(entity).into_iter().flat_map(|children| children.iter()));
+    }
+
+    // Start from the joints whose parent isn't one, in a stable order so the list doesn't change
+    let mut roots: Vec<Entity> = skin_joints.iter().copied()
+        .filter(|joint| parent_query.get(*joint).map_or(true, |parent| !skin_joints.contains(&parent.get())))
+        .collect();
+    roots.sort();
+    let mut joints = Vec::new();
+    for root in roots {
+        add_joint(root, None, 0, &skin_joints, children_query, &mut joints);
+    }
+    joints
+}
+
+fn skeleton_debug_draw(
+    mut lines: ResMut<DebugLines>,
+    mut query: Query<(Entity, &mut SkeletonDebug)>,
+    hierarchy_changed_query: Query<(), Or<(Changed<Children>, Changed<SkinnedMesh>)>>,
+    children_query: Query<&Children>,
+    parent_query: Query<&Parent>,
+    skinned_query: Query<&SkinnedMesh>,
+    names_query: Query<&Name>,
+    global_transforms: Query<&GlobalTransform>,
+){
+    let hierarchy_changed = !hierarchy_changed_query.is_empty();
+    for (character, mut skeleton_debug) in query.iter_mut() {
+        // Only touch the component when the joints change, it is shown in the inspector
+        if hierarchy_changed || skeleton_debug.joint_entities.is_empty() {
+            let joint_entities = find_joints(character, &children_query, &parent_query, &skinned_query);
+            if joint_entities != skeleton_debug.joint_entities {
+                skeleton_debug.joints = joint_entities.iter()
+                    .map(|joint| format!("{}{}", "  ".repeat(joint.depth),
+                        names_query.get(joint.entity).map_or("<unnamed>".to_string(), |name| name.to_string())))
+                    .collect();
+                skeleton_debug.joint_entities = joint_entities;
+            }
+        }
+        if !skeleton_debug.enabled {
+            continue;
+        }
+
+        let length = skeleton_debug.axis_length;
+        for joint in skeleton_debug.joint_entities.iter() {
+            let transform = match global_transforms.get(joint.entity) {
+                Ok(transform) => transform,
+                Err(_) => continue,
+            };
+            let (_, rotation, position) = transform.to_scale_rotation_translation();
+            lines.line_colored(position, position + rotation * Vec3::X * length, 0., Color::RED);
+            lines.line_colored(position, position + rotation * Vec3::Y * length, 0., Color::GREEN);
+            lines.line_colored(position, position + rotation * Vec3::Z * length, 0., Color::BLUE);
+            if let Some(parent_transform) = joint.parent.and_then(|parent| global_transforms.get(parent).ok()) {
+                lines.line_gradient(parent_transform.translation(), position, 0., Color::WHITE, Color::YELLOW);
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use bevy_prototype_debug_lines::*;
+use bevy_playground::collision::{Collider, ray_cast};
+use rand::{thread_rng, Rng};
+use crate::{
+    Speed,
+    controller::{CollisionWorld, character_controller_update},
+};
+
+// Steering behaviours, Reynolds style, for characters that aren't driven by input.
+// Each behaviour asks for a steering force, the change from the current velocity to the velocity
+// it would like. The weighted sum is limited to max_force and accelerates Speed, limited to max_speed.
+// All on the ground plane, the character controller does the walking and the colliding.
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin{
+    fn build(&self, app: &mut App) {
+        app.register_type::<Steering>()
+        .add_system(steering_update.before(character_controller_update));
+    }
+}
+
+// Obstacle feelers are cast this far above the feet, over anything the controller steps up
+const FEELER_HEIGHT: f32 = 0.3;
+// Spread of the side feelers from the direction of travel, radians
+const FEELER_SPREAD: f32 = 0.5;
+// Surfaces whose normal points up more than this are walked on, not avoided
+const WALKABLE_NORMAL_Y: f32 = 0.7;
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug)]
+pub enum SteeringTarget {
+    Point(Vec3),
+    Entity(Entity),
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug)]
+pub enum SteeringBehaviour {
+    Seek(SteeringTarget),
+    Flee {target: SteeringTarget, panic_distance: f32},       // Only runs when the target is closer than this
+    Arrive {target: SteeringTarget, slowing_distance: f32, stand_off: f32}, // Slows down within slowing_distance, stops stand_off short
+    Wander {distance: f32, radius: f32, jitter: f32},         // Circle ahead, jitter in radians per second
+    Separation {radius: f32},                                 // From the other characters with a Speed
+    ObstacleAvoidance {look_ahead: f32},                      // From the static colliders
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, Debug)]
+pub struct WeightedBehaviour {
+    pub behaviour: SteeringBehaviour,
+    pub weight: f32,
+}
+
+impl WeightedBehaviour {
+    pub fn new(behaviour: SteeringBehaviour, weight: f32) -> Self {
+        Self {behaviour, weight}
+    }
+}
+
+#[derive(Reflect, Component, Clone, Debug)]
+#[reflect(Component)]
+pub struct Steering {
+    pub enabled: bool,
+    pub max_speed: f32,
+    pub max_force: f32, // Acceleration, how quickly it can change course
+    pub behaviours: Vec<WeightedBehaviour>,
+    pub debug: bool,    // Draw the velocity and steering force
+    wander_angle: f32,
+}
+impl Default for Steering
+{
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_speed: 2.,
+            max_force: 6.,
+            behaviours: Vec::new(),
+            debug: false,
+            wander_angle: 0.,
+        }
+    }
+}
+
+fn flat(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, 0., v.z)
+}
+
+// Full speed towards the target
+pub fn seek(position: Vec3, velocity: Vec3, target: Vec3, max_speed: f32) -> Vec3 {
+    flat(target - position).normalize_or_zero() * max_speed - velocity
+}
+
+// Full speed away from the threat, while it is within panic_distance
+pub fn flee(position: Vec3, velocity: Vec3, threat: Vec3, max_speed: f32, panic_distance: f32) -> Vec3 {
+    let away = flat(position - threat);
+    if away.length_squared() > panic_distance * panic_distance {
+        return Vec3::ZERO;
+    }
+    away.normalize_or_zero() * max_speed - velocity
+}
+
+// Seek that slows down linearly within slowing_distance and stops stand_off short of the target
+pub fn arrive(position: Vec3, velocity: Vec3, target: Vec3, max_speed: f32, slowing_distance: f32, stand_off: f32) -> Vec3 {
+    let offset = flat(target - position);
+    let distance = offset.length() - stand_off;
+    if distance < 0.01 {
+        return -velocity;
+    }
+    let ramped_speed = max_speed * (distance / slowing_distance).min(1.);
+    offset.normalize() * ramped_speed - velocity
+}
+
+// Seek a point on a circle ahead, wander_angle around the circle, measured from straight ahead
+pub fn wander(velocity: Vec3, forward: Vec3, wander_angle: f32, distance: f32, radius: f32, max_speed: f32) -> Vec3 {
+    let forward = flat(forward).normalize_or_zero();
+    let point = forward * distance + Quat::from_rotation_y(wander_angle) * forward * radius;
+    point.normalize_or_zero() * max_speed - velocity
+}
+
+// Away from the neighbours within radius, harder the closer they are
+pub fn separation(position: Vec3, neighbours: impl Iterator<Item = Vec3>, radius: f32, max_speed: f32) -> Vec3 {
+    let mut force = Vec3::ZERO;
+    for neighbour in neighbours {
+        let away = flat(position - neighbour);
+        let distance = away.length();
+        if distance > 0.0001 && distance < radius {
+            force += away / distance * (1. - distance / radius);
+        }
+    }
+    force * max_speed
+}
+
+// Away from the walls the feelers hit, harder the closer the hit.
+// Walkable slopes aren't obstacles, the controller walks up them.
+pub fn avoid_obstacles(
+    position: Vec3,
+    direction: Vec3,
+    look_ahead: f32,
+    max_speed: f32,
+    colliders: &[Collider],
+) -> Vec3 {
+    let direction = flat(direction).normalize_or_zero();
+    let origin = position + Vec3::Y * FEELER_HEIGHT;
+    let mut force = Vec3::ZERO;
+    for angle in [0., FEELER_SPREAD, -FEELER_SPREAD] {
+        let feeler = Quat::from_rotation_y(angle) * direction;
+        if let Some(hit) = ray_cast(colliders, origin, feeler, look_ahead) {
+            if hit.normal.y < WALKABLE_NORMAL_Y {
+                force += flat(hit.normal).normalize_or_zero() * (1. - hit.distance / look_ahead);
+            }
+        }
+    }
+    force * max_speed
+}
+
+fn steering_update(
+    time: Res<Time>,
+    mut lines: ResMut<DebugLines>,
+    mut steering_query: Query<(Entity, &mut Steering, &mut Speed, &mut Transform)>,
+    movers_query: Query<(Entity, &GlobalTransform), With<Speed>>,
+    global_transforms: Query<&GlobalTransform>,
+    collision_world: Res<CollisionWorld>,
+){
+    let dt = time.delta_seconds();
+    let mut rng = thread_rng();
+    let target_position = |target: &SteeringTarget| match target {
+        SteeringTarget::Point(point) => Some(*point),
+        SteeringTarget::Entity(entity) => global_transforms.get(*entity).ok().map(|transform| transform.translation()),
+    };
+    for (entity, mut steering, mut speed, mut transform) in steering_query.iter_mut() {
+        if !steering.enabled {
+            continue;
+        }
+        let position = transform.translation;
+        let velocity = flat(speed.0);
+        let forward = transform.forward();
+        let max_speed = steering.max_speed;
+
+        let mut wander_angle = steering.wander_angle;
+        let mut force = Vec3::ZERO;
+        for weighted in steering.behaviours.iter() {
+            let behaviour_force = match weighted.behaviour {
+                SteeringBehaviour::Seek(target) => target_position(&target)
+                    .map_or(Vec3::ZERO, |target| seek(position, velocity, target, max_speed)),
+                SteeringBehaviour::Flee {target, panic_distance} => target_position(&target)
+                    .map_or(Vec3::ZERO, |target| flee(position, velocity, target, max_speed, panic_distance)),
+                SteeringBehaviour::Arrive {target, slowing_distance, stand_off} => target_position(&target)
+                    .map_or(Vec3::ZERO, |target| arrive(position, velocity, target, max_speed, slowing_distance, stand_off)),
+                SteeringBehaviour::Wander {distance, radius, jitter} => {
+                    wander_angle += rng.gen_range(-1f32..1.) * jitter * dt;
+                    wander(velocity, forward, wander_angle, distance, radius, max_speed)
+                }
+                SteeringBehaviour::Separation {radius} => separation(
+                    position,
+                    movers_query.iter()
+                        .filter(|(other, _)| *other != entity)
+                        .map(|(_, transform)| transform.translation()),
+                    radius,
+                    max_speed),
+                SteeringBehaviour::ObstacleAvoidance {look_ahead} => avoid_obstacles(
+                    position,
+                    if velocity.length_squared() > 0.01 {velocity} else {forward},
+                    look_ahead,
+                    max_speed,
+                    &collision_world.0),
+            };
+            force += behaviour_force * weighted.weight;
+        }
+        steering.wander_angle = wander_angle;
+
+        // Accelerate
+        let force = force.clamp_length_max(steering.max_force);
+        speed.0 = (velocity + force * dt).clamp_length_max(max_speed);
+
+        // Point in velocity direction, with a little inertia like the player
+        let speed_magnitude = speed.0.length();
+        if speed_magnitude > 0.1 {
+            transform.rotation = transform.rotation.slerp(
+                transform.looking_at(transform.translation + speed.0 / speed_magnitude, Vec3::Y).rotation,
+                (dt * 5.).min(1.));
+        }
+
+        if steering.debug {
+            lines.line_gradient(position, position + speed.0, 0., Color::RED, Color::YELLOW);
+            lines.line_gradient(position, position + force, 0., Color::GREEN, Color::ORANGE);
+        }
+    }
+}
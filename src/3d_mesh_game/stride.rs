@@ -0,0 +1,291 @@
+use bevy::{asset::LoadState, math::Affine3A, prelude::*, utils::HashMap};
+use crate::{
+    animation::AnimationLink,
+    animation_state::AnimationStateMachine,
+    character::{Character, CharacterDefinition},
+    pose::{BonePose, sample_curve, find_named_descendant},
+};
+
+// Stride matching, the alternative to root motion for clips that are authored in place.
+// Each locomotion clip is analysed once: while a foot is planted it slides backwards under
+// the body at the ground speed the clip was made for. Playing the clip at
+// ground speed / stride distance cycles per second keeps the planted foot from sliding.
+
+pub struct StridePlugin;
+
+impl Plugin for StridePlugin{
+    fn build(&self, app: &mut App) {
+        app.add_system(stride_analysis);
+    }
+}
+
+// Samples per clip cycle for the analysis
+const STRIDE_SAMPLES: usize = 64;
+// A foot counts as planted within this fraction of its height range above its lowest point
+const PLANTED_HEIGHT_FRACTION: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClipStride {
+    pub distance: f32, // Ground covered in one cycle of the clip
+    pub duration: f32, // Length of one cycle
+}
+
+impl ClipStride {
+    // Clip playback rate for a ground speed, cycles per second times the cycle length
+    pub fn rate(&self, ground_speed: f32) -> f32 {
+        ground_speed / self.distance * self.duration
+    }
+}
+
+// Stride of the character's locomotion clips, by the clip names the animation graph uses.
+// None for clips whose stride couldn't be measured, those play as authored.
+#[derive(Component, Default)]
+pub struct Strides(pub HashMap<String, Option<ClipStride>>);
+
+// One bone from the animation root down to a foot, with its rest transform for unanimated channels
+pub struct ChainBone {
+    pub path: EntityPath,
+    pub rest: Transform,
+}
+
+// Position of the end of the chain at time, in the space of the chain's parent
+fn chain_end_position(clip: &AnimationClip, chain: &[ChainBone], time: f32) -> Vec3 {
+    let mut affine = Affine3A::IDENTITY;
+    for bone in chain.iter() {
+        let mut pose = BonePose::default();
+        for curve in clip.curves().get(&bone.path).into_iter().flatten() {
+            sample_curve(curve, time, &mut pose);
+        }
+        let local = Transform {
+            translation: pose.translation.unwrap_or(bone.rest.translation),
+            rotation: pose.rotation.unwrap_or(bone.rest.rotation),
+            scale: pose.scale.unwrap_or(bone.rest.scale),
+        };
+        affine = affine * local.compute_affine();
+    }
+    affine.translation.into()
+}
+
+// Measure the stride of a clip from its foot chains.
+// chain_to_character maps the chains' parent space to a space with the character's up as Y and
+// world units, so the stride comes out in world units.
+// Returns None if no foot is ever planted, like in an idle clip.
+pub fn measure_stride(clip: &AnimationClip, feet: &[Vec<ChainBone>], chain_to_character: &Affine3A) -> Option<ClipStride> {
+    let duration = clip.duration();
+    if duration <= 0. {
+        return None;
+    }
+    let dt = duration / STRIDE_SAMPLES as f32;
+    let mut planted_distance = 0.;
+    let mut planted_time = 0.;
+    for chain in feet.iter().filter(|chain| !chain.is_empty()) {
+        let positions: Vec<Vec3> = (0..=STRIDE_SAMPLES)
+            .map(|sample| chain_to_character.transform_point3(chain_end_position(clip, chain, sample as f32 * dt)))
+            .collect();
+        let lowest = positions.iter().map(|position| position.y).fold(f32::MAX, f32::min);
+        let highest = positions.iter().map(|position| position.y).fold(f32::MIN, f32::max);
+        let planted_height = lowest + (highest - lowest) * PLANTED_HEIGHT_FRACTION;
+        // Horizontal slide of the foot between samples where it is planted at both ends
+        for pair in positions.windows(2) {
+            if pair[0].y <= planted_height && pair[1].y <= planted_height {
+                planted_distance += Vec2::new(pair[1].x - pair[0].x, pair[1].z - pair[0].z).length();
+                planted_time += dt;
+            }
+        }
+    }
+    if planted_time <= 0. || planted_distance <= 0. {
+        return None;
+    }
+    let ground_speed = planted_distance / planted_time;
+    Some(ClipStride {
+        distance: ground_speed * duration,
+        duration,
+    })
+}
+
+// The chain of bones from the animation root down to foot, with the paths the clips use
+fn foot_chain(
+    root: Entity,
+    foot: Entity,
+    parent_query: &Query<&Parent>,
+    names_query: &Query<&Name>,
+    transforms_query: &Query<&Transform>,
+) -> Option<Vec<ChainBone>> {
+    let mut entities = vec![foot];
+    while *entities.last().unwrap() != root {
+        entities.push(parent_query.get(*entities.last().unwrap()).ok()?.get());
+    }
+    entities.reverse();
+
+    let mut chain = Vec::new();
+    let mut parts = Vec::new();
+    for entity in entities {
+        parts.push(names_query.get(entity).ok()?.clone());
+        chain.push(ChainBone {
+            path: EntityPath { parts: parts.clone() },
+            rest: *transforms_query.get(entity).ok()?,
+        });
+    }
+    // The root's own transform isn't part of the clip space, the chain starts below it
+    chain.remove(0);
+    Some(chain)
+}
+
+// Analyse a character's locomotion clips once, when its skeleton and clips are available
+fn stride_analysis(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definitions: Res<Assets<CharacterDefinition>>,
+    clips: Res<Assets<AnimationClip>>,
+    characters_query: Query<(Entity, &Character, &AnimationStateMachine, &AnimationLink, &GlobalTransform), Without<Strides>>,
+    children_query: Query<&Children>,
+    parent_query: Query<&Parent>,
+    names_query: Query<&Name>,
+    transforms_query: Query<&Transform>,
+    global_transforms: Query<&GlobalTransform>,
+){
+    for (entity, character, state_machine, anim_link, character_global) in characters_query.iter() {
+        let definition = match definitions.get(&character.0) {
+            Some(definition) => definition,
+            None => continue,
+        };
+        let root = match state_machine.part.as_ref()
+            .map_or(anim_link.players.values().next().copied(), |part| anim_link.get(part)) {
+            Some(root) => root,
+            None => continue,
+        };
+        let root_global = match global_transforms.get(root) {
+            Ok(root_global) => root_global,
+            Err(_) => continue,
+        };
+        let loading = definition.locomotion_clips.iter()
+            .filter_map(|name| state_machine.clips.get(name))
+            .any(|handle| clips.get(handle).is_none() && asset_server.get_load_state(handle) != LoadState::Failed);
+        // Clips still loading, the ones that failed or are missing are left unmeasured below
+        if loading {
+            continue;
+        }
+
+        // The scene is spawned once its animation player is linked, feet it doesn't have won't turn up
+        let feet: Option<Vec<Vec<ChainBone>>> = definition.feet.iter()
+            .map(|foot| find_named_descendant(root, foot, &children_query, &names_query)
+                .and_then(|foot| foot_chain(root, foot, &parent_query, &names_query, &transforms_query)))
+            .collect();
+        if feet.is_none() {
+            warn!("Feet {:?} not found below the animation root, can't match strides", definition.feet);
+        }
+        let locomotion_clips: Vec<(&String, Option<&AnimationClip>)> = definition.locomotion_clips.iter()
+            .map(|name| {
+                let clip = state_machine.clips.get(name).and_then(|handle| clips.get(handle));
+                if clip.is_none() {
+                    warn!("Locomotion clip \"{}\" is missing or failed to load, can't match its stride", name);
+                }
+                (name, clip)
+            })
+            .collect();
+
+        // World units and directions, relative to the character
+        let (_, character_rotation, character_translation) = character_global.to_scale_rotation_translation();
+        let chain_to_character = Affine3A::from_rotation_translation(character_rotation, character_translation).inverse()
+            * root_global.affine();
+
+        let mut strides = Strides::default();
+        for (name, clip) in locomotion_clips {
+            let (clip, feet) = match (clip, feet.as_ref()) {
+                (Some(clip), Some(feet)) => (clip, feet),
+                _ => {
+                    strides.0.insert(name.clone(), None);
+                    continue;
+                }
+            };
+            match measure_stride(clip, feet, &chain_to_character) {
+                Some(stride) => {
+                    info!("Clip \"{}\" covers {:.2} per cycle of {:.2}s", name, stride.distance, stride.duration);
+                    strides.0.insert(name.clone(), Some(stride));
+                }
+                None => {
+                    warn!("Clip \"{}\" never plants a foot of {:?}, can't match its stride", name, definition.feet);
+                    strides.0.insert(name.clone(), None);
+                }
+            }
+        }
+        commands.entity(entity).insert(strides);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::animation::{Keyframes, VariableCurve};
+    use super::*;
+
+    fn path(names: &[&str]) -> EntityPath {
+        EntityPath { parts: names.iter().map(|name| Name::new(name.to_string())).collect() }
+    }
+
+    // A hip with a foot under it, the foot's translation keyed as given
+    fn leg(clip: &mut AnimationClip, times: Vec<f32>, positions: Vec<Vec3>) -> Vec<ChainBone> {
+        clip.add_curve_to_path(path(&["hip", "foot"]), VariableCurve {
+            keyframe_timestamps: times,
+            keyframes: Keyframes::Translation(positions),
+        });
+        vec![
+            ChainBone { path: path(&["hip"]), rest: Transform::from_xyz(0., 1., 0.) },
+            ChainBone { path: path(&["hip", "foot"]), rest: Transform::from_xyz(0., -1., 0.) },
+        ]
+    }
+
+    // One second cycle: planted for half a second sliding back 1 unit, then lifted and swung forward.
+    // The lift and drop take exactly one analysis sample so no lifted sample counts as planted.
+    fn walk_leg(clip: &mut AnimationClip) -> Vec<ChainBone> {
+        let sample = 1. / STRIDE_SAMPLES as f32;
+        leg(clip,
+            vec![0., 0.5, 0.5 + sample, 1. - sample, 1.],
+            vec![
+                Vec3::new(0., -1., 0.5),
+                Vec3::new(0., -1., -0.5),
+                Vec3::new(0., 0., -0.5),
+                Vec3::new(0., 0., 0.5),
+                Vec3::new(0., -1., 0.5),
+            ])
+    }
+
+    #[test]
+    fn stride_of_walk_cycle() {
+        let mut clip = AnimationClip::default();
+        let feet = vec![walk_leg(&mut clip)];
+        let stride = measure_stride(&clip, &feet, &Affine3A::IDENTITY).unwrap();
+        // Ground speed 2 for a cycle of 1s
+        assert!((stride.distance - 2.).abs() < 1e-4, "distance {}", stride.distance);
+        assert!((stride.duration - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stride_in_character_units() {
+        let mut clip = AnimationClip::default();
+        let feet = vec![walk_leg(&mut clip)];
+        let stride = measure_stride(&clip, &feet, &Affine3A::from_scale(Vec3::splat(0.5))).unwrap();
+        assert!((stride.distance - 1.).abs() < 1e-4, "distance {}", stride.distance);
+    }
+
+    #[test]
+    fn no_stride_without_planted_foot() {
+        // Idle, the foot never slides
+        let mut clip = AnimationClip::default();
+        let feet = vec![leg(&mut clip,
+            vec![0., 0.5, 1.],
+            vec![Vec3::new(0., -1., 0.), Vec3::new(0., -0.9, 0.), Vec3::new(0., -1., 0.)])];
+        assert!(measure_stride(&clip, &feet, &Affine3A::IDENTITY).is_none());
+
+        // No keys at all
+        assert!(measure_stride(&AnimationClip::default(), &feet, &Affine3A::IDENTITY).is_none());
+    }
+
+    #[test]
+    fn rate_for_ground_speed() {
+        let stride = ClipStride { distance: 2., duration: 1.5 };
+        // 4 per second over 2 per cycle is 2 cycles per second, of 1.5s each
+        assert!((stride.rate(4.) - 3.).abs() < 1e-6);
+        assert!((stride.rate(2. / 1.5) - 1.).abs() < 1e-6);
+        assert_eq!(stride.rate(0.), 0.);
+    }
+}
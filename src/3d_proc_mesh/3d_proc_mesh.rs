@@ -6,11 +6,9 @@
 
 // Project module declaration (same as file names)
 mod proc_mesh;
-mod camera;
 
 // Includes from project modules
 use proc_mesh::ProcMeshPlugin;
-use camera::CameraPlugin;
 
 // External includes
 
@@ -21,21 +19,23 @@ use std::{
 // Bevy includes
 
 use bevy::{
-    pbr::{MaterialPipeline, MaterialPipelineKey},
     pbr::wireframe::{WireframePlugin},
     prelude::*,
-    reflect::{TypeUuid,        // For material id
-        Reflect, TypeRegistry},// For reflecting data to egui
-    render::{
-        mesh::MeshVertexBufferLayout,
-        render_resource::{
-            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
-        },
-    }
+    reflect::{Reflect, TypeRegistry},// For reflecting data to egui
 };
 use bevy_prototype_debug_lines::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+// Shared playground includes
+
+use bevy_playground::{
+    camera_path::{CameraPathPlayer, CameraPathPlugin, camera_path_update},
+    camera_rig::{CameraMode, CameraRig, CameraRigPlugin, camera_rig_update},
+    camera_shake::{CameraShake, CameraShakePlugin},
+    custom_material::MyCustomMaterial,
+    mouse_look::{FollowDistance, MouseLookPlugin},
+};
+
 // Component types
 
 #[derive(Component)]
@@ -47,33 +47,12 @@ struct Camera;
 #[derive(Component)]
 struct FloorTile;
 
-#[derive(Reflect, Component)]
-#[reflect(Component)]
-struct Speed(Vec3);
-impl Default for Speed
-{
-    fn default() -> Self {
-        Self(Vec3::splat(0.))
-    }
-}
-
-#[derive(Component)]
-struct CameraRotation(Vec2);
-impl Default for CameraRotation
-{
-    fn default() -> Self {
-        Self(Vec2::splat(0.))
-    }
-}
 
 
 // Global constants
 const MARBLE_RADIUS: f32 = 1.;
 const FLOOR_SIZE: Vec3 = Vec3::new(80., 8., 80.);
 const FLOOR_POSITION: Vec3 = Vec3::new(0., -FLOOR_SIZE.y * 0.5, 0.);
-const GAMEPAD_DEADZONE: f32 = 0.1;
-const GAMEPAD_AXIS_L_SENSITIVITY: f32 = 5.5;
-const GAMEPAD_AXIS_R_SENSITIVITY: f32 = 5.5;
 
 // App entry point
 
@@ -87,13 +66,16 @@ fn main() {
             }))
         .add_plugin(MaterialPlugin::<MyCustomMaterial>::default())
         .add_plugin(ProcMeshPlugin)
-        .add_plugin(CameraPlugin)
+        .add_plugin(CameraRigPlugin::default())
+        .add_plugin(MouseLookPlugin)
+        .add_plugin(CameraPathPlugin)
+        .add_plugin(CameraShakePlugin::default()
+            .after(camera_rig_update)
+            .after(camera_path_update))
         .add_plugin(DebugLinesPlugin::with_depth_test(true))
         .add_plugin(WireframePlugin)
         .add_plugin(WorldInspectorPlugin)
         .add_startup_system(setup)
-        // EGUI Type registry
-        .register_type::<Speed>()
         // Let's go
         .run();
 }
@@ -106,6 +88,20 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
+    // Make a camera, flying around the planet or orbiting it all the way over
+    let mut rig = CameraRig::new(vec![CameraMode::FreeFly, CameraMode::Orbit]);
+    rig.orbit.pitch_max = 1.;
+    rig.orbit.distance = FollowDistance {distance: 40., min: 20., max: 100.};
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(0., 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    })
+    // Custom components
+    .insert(rig)
+    .insert(CameraPathPlayer::new(asset_server.load("paths/proc_mesh.campath.ron")))
+    .insert(CameraShake::default())
+    .insert(Camera);
+
     /*
     commands.spawn(PointLightBundle {
         point_light: PointLight {
@@ -155,52 +151,3 @@ fn setup(
     debug!("x: {}, state: {:?}", 0.1, "test");
     trace!("entity transform: {:?}", Transform::from_xyz(-2.0, 2.5, 5.0));*/
 }
-
-
-
-// Shader buffer bindings
-// https://docs.rs/bevy/0.8.0/bevy/render/render_resource/trait.AsBindGroup.html
-#[derive(AsBindGroup, Clone, TypeUuid)]
-#[uuid = "69196246-07cd-4581-9885-167958593672"]
-pub struct MyCustomMaterial {
-    #[uniform(0)]
-    color: Color,
-    #[uniform(0)]
-    time: f32,
-    #[texture(1)]
-    #[sampler(2)]
-    color_texture: Option<Handle<Image>>,
-    #[texture(3)]
-    #[sampler(4)]
-    noise_texture: Option<Handle<Image>>,
-    alpha_mode: AlphaMode,
-}
-
-// Implement the material trait for our custom material struct in order to make it compliant with shader pipeline.
-// Override the behaviours for which we don't want the default behaviours.
- impl Material for MyCustomMaterial {
-    fn vertex_shader() -> ShaderRef {
-        "shaders/custom_material.vert".into()
-    }
-
-    fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material_marble.frag".into()
-    }
-
-    fn alpha_mode(&self) -> AlphaMode {
-        self.alpha_mode
-    }
-
-    // Specify shader program entrypoint overrides (not needed for WGSL)
-    fn specialize(
-        _pipeline: &MaterialPipeline<Self>,
-        descriptor: &mut RenderPipelineDescriptor,
-        _layout: &MeshVertexBufferLayout,
-        _key: MaterialPipelineKey<Self>,
-    ) -> Result<(), SpecializedMeshPipelineError> 
-    {
-        descriptor.vertex.entry_point = "main".into();
-        descriptor.fragment.as_mut().unwrap().entry_point = "main".into();
-        Ok(())
-    }
- }
\ No newline at end of file
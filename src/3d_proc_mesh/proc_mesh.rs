@@ -7,6 +7,7 @@ use bevy::{
     pbr::wireframe::{Wireframe, WireframeConfig},
 };
 use crate::{ProcMesh, MyCustomMaterial};
+use bevy_playground::camera_rig::CameraTarget;
 use bevy_prototype_debug_lines::*;
 use fast_surface_nets::glam::{Vec2, Vec3A};
 use fast_surface_nets::ndshape::{ConstShape, ConstShape3u32};
@@ -77,10 +78,14 @@ fn gen_mesh(
         transform: Transform::from_xyz(0.0, 0., 0.0).with_scale(Vec3::splat(1.0)),
         /*material: materials.add(MyCustomMaterial {
             color: Color::GREEN,
-            time: 0.0,
             color_texture: Some(asset_server.load("block.png")),
             noise_texture: Some(asset_server.load("manifold_noise.png")),
-            alpha_mode: AlphaMode::Opaque,
+            features: CustomMaterialFeatures {
+                marble_noise: true,
+                lighting: CustomLighting::Pbr,
+                ..default()
+            },
+            ..default()
         }*/
         material: materials.add( StandardMaterial {
             base_color:         Color::SEA_GREEN,
@@ -91,6 +96,8 @@ fn gen_mesh(
     },
     Wireframe))
     // Custom components
+    // The mesh is in voxels, its middle is half a chunk in
+    .insert(CameraTarget {offset: Vec3::splat(16.)})
     .insert(ProcMesh);
 }
 
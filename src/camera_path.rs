@@ -0,0 +1,367 @@
+use std::{error::Error, fs, ops::{Add, Mul}, path::PathBuf};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, FileAssetIo, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use bevy_prototype_debug_lines::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use crate::camera_rig::{CameraMode, CameraRig, camera_rig_update};
+
+// Spline camera paths for cinematics and flythroughs. A path is timed keyframes of camera position,
+// look target and FOV in a .campath.ron file, interpolated as a Catmull-Rom or Bezier spline.
+// A CameraPathPlayer on a camera plays its path over whatever the camera rig does.
+//
+// Paths are made with the free fly camera: K records a keyframe where the camera is, Backspace
+// drops the last one and F5 saves the path back over its file. On Bezier paths K also adds the
+// two control points before the keyframe, on the straight line from the previous one.
+// P plays the path or stops it, when it stops the rig blends back in.
+// The path is drawn with debug lines while it isn't playing.
+
+pub struct CameraPathPlugin;
+
+impl Plugin for CameraPathPlugin{
+    fn build(&self, app: &mut App) {
+        app.add_asset::<CameraPath>()
+        .add_asset_loader(CameraPathLoader)
+        .register_type::<CameraPathPlayer>()
+        .add_system(camera_path_update.after(camera_rig_update))
+        .add_system(camera_path_debug_draw.after(camera_path_update));
+    }
+}
+
+// Where the asset server finds files, AssetPlugin's default
+const ASSET_FOLDER: &str = "assets";
+// Seconds between points when drawing the path
+const DEBUG_STEP: f32 = 0.1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PathInterpolation {
+    CatmullRom, // Through every keyframe
+    Bezier,     // Through every third keyframe, the two in between are control points
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct CameraKeyframe {
+    pub time: f32,      // Seconds from the start of the path
+    pub position: Vec3,
+    pub target: Vec3,   // Point looked at
+    pub fov: f32,       // Vertical, radians
+}
+
+// The file format is the asset as it is, keyframes in time order
+#[derive(Serialize, Deserialize, TypeUuid, Clone, Debug)]
+#[uuid = "40975e19-a787-4747-919f-95822d56e40c"]
+#[serde(default)]
+pub struct CameraPath {
+    pub interpolation: PathInterpolation,
+    pub looping: bool, // For a smooth loop make the last keyframe the same as the first
+    pub keyframes: Vec<CameraKeyframe>,
+}
+impl Default for CameraPath
+{
+    fn default() -> Self {
+        Self {
+            interpolation: PathInterpolation::CatmullRom,
+            looping: false,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |keyframe| keyframe.time)
+    }
+
+    // The camera at a time along the path, None without keyframes
+    pub fn sample(&self, time: f32) -> Option<CameraKeyframe> {
+        let keys = &self.keyframes;
+        let (first, last) = (keys.first()?, keys.last()?);
+        let time = if self.looping && last.time > first.time {
+            first.time + (time - first.time).rem_euclid(last.time - first.time)
+        } else {
+            time.clamp(first.time, last.time)
+        };
+
+        // The four keyframes of the segment the time is in
+        let segment = match self.interpolation {
+            PathInterpolation::CatmullRom => {
+                if keys.len() < 2 {
+                    return Some(*first);
+                }
+                let last = keys.len() - 1;
+                let i = keys.iter().rposition(|key| key.time <= time).unwrap_or(0).min(last - 1);
+                // Looping, the last keyframe is the first again, so the neighbours wrap around past it
+                let wrap = self.looping && last > 1;
+                let previous = match i {
+                    0 if wrap => keys[last - 1],
+                    0 => keys[0],
+                    _ => keys[i - 1],
+                };
+                let next = match keys.get(i + 2) {
+                    Some(next) => *next,
+                    None if wrap => keys[1],
+                    None => keys[last],
+                };
+                [previous, keys[i], keys[i + 1], next]
+            }
+            PathInterpolation::Bezier => {
+                let segments = (keys.len() - 1) / 3;
+                if segments == 0 {
+                    return Some(*first);
+                }
+                let i = (0..segments).rev().find(|i| keys[i * 3].time <= time).unwrap_or(0) * 3;
+                [keys[i], keys[i + 1], keys[i + 2], keys[i + 3]]
+            }
+        };
+        let (start, end) = match self.interpolation {
+            PathInterpolation::CatmullRom => (segment[1].time, segment[2].time),
+            PathInterpolation::Bezier => (segment[0].time, segment[3].time),
+        };
+        let t = if end > start {((time - start) / (end - start)).clamp(0., 1.)} else {1.};
+        Some(CameraKeyframe {
+            time,
+            position: self.interpolate(segment.map(|key| key.position), t),
+            target: self.interpolate(segment.map(|key| key.target), t),
+            fov: self.interpolate(segment.map(|key| key.fov), t),
+        })
+    }
+
+    fn interpolate<T>(&self, points: [T; 4], t: f32) -> T
+    where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+        match self.interpolation {
+            PathInterpolation::CatmullRom => catmull_rom(points, t),
+            PathInterpolation::Bezier => bezier(points, t),
+        }
+    }
+}
+
+// Uniform Catmull-Rom between the middle two points
+pub fn catmull_rom<T>([p0, p1, p2, p3]: [T; 4], t: f32) -> T
+where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.
+        + (p2 + p0 * -1.) * t
+        + (p0 * 2. + p1 * -5. + p2 * 4. + p3 * -1.) * t2
+        + (p0 * -1. + p1 * 3. + p2 * -3. + p3) * t3) * 0.5
+}
+
+// Cubic Bezier from the first point to the last
+pub fn bezier<T>([p0, p1, p2, p3]: [T; 4], t: f32) -> T
+where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+    let u = 1. - t;
+    p0 * (u * u * u) + p1 * (3. * u * u * t) + p2 * (3. * u * t * t) + p3 * (t * t * t)
+}
+
+#[derive(Default)]
+pub struct CameraPathLoader;
+
+impl AssetLoader for CameraPathLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let path = ron::de::from_bytes::<CameraPath>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campath.ron"]
+    }
+}
+
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct CameraPathPlayer {
+    pub path: Handle<CameraPath>,
+    pub playing: bool,
+    pub time: f32,
+    pub speed: f32,           // Playback rate, 1 is as authored
+    pub key_interval: f32,    // Seconds between recorded keyframes, retime them in the file
+    pub target_distance: f32, // Recorded look targets are this far in front of the camera
+    pub debug: bool,
+    rig_fov: Option<f32>,     // To go back to after playing
+}
+impl Default for CameraPathPlayer
+{
+    fn default() -> Self {
+        Self {
+            path: Handle::default(),
+            playing: false,
+            time: 0.,
+            speed: 1.,
+            key_interval: 2.,
+            target_distance: 10.,
+            debug: true,
+            rig_fov: None,
+        }
+    }
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: Handle<CameraPath>) -> Self {
+        Self {
+            path,
+            ..default()
+        }
+    }
+
+    fn stop(&mut self, projection: &mut Projection, rig: Option<&mut CameraRig>) {
+        self.playing = false;
+        if let Some(fov) = self.rig_fov.take() {
+            set_fov(projection, fov);
+        }
+        if let Some(rig) = rig {
+            rig.resume();
+        }
+    }
+}
+
+fn fov(projection: &Projection) -> Option<f32> {
+    match projection {
+        Projection::Perspective(perspective) => Some(perspective.fov),
+        Projection::Orthographic(_) => None,
+    }
+}
+
+fn set_fov(projection: &mut Projection, fov: f32) {
+    if let Projection::Perspective(perspective) = projection {
+        perspective.fov = fov;
+    }
+}
+
+// Write a path back over the file it was loaded from
+fn save(asset_server: &AssetServer, handle: &Handle<CameraPath>, path: &CameraPath) -> Result<PathBuf, Box<dyn Error>> {
+    let asset_path = asset_server.get_handle_path(handle).ok_or("the camera path wasn't loaded from a file")?;
+    let file = FileAssetIo::get_base_path().join(ASSET_FOLDER).join(asset_path.path());
+    fs::write(&file, ron::ser::to_string_pretty(path, PrettyConfig::default())?)?;
+    Ok(file)
+}
+
+pub fn camera_path_update(
+    time: Res<Time>,
+    kb_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut paths: ResMut<Assets<CameraPath>>,
+    mut cameras_query: Query<(&mut Transform, &mut Projection, &mut CameraPathPlayer, Option<&mut CameraRig>)>,
+){
+    for (mut transform, mut projection, mut player, mut rig) in cameras_query.iter_mut() {
+        // Recording, from the free fly camera only
+        let flying = rig.as_ref().map_or(true, |rig| rig.mode == CameraMode::FreeFly);
+        if flying && !player.playing {
+            if kb_input.just_pressed(KeyCode::K) {
+                // A path that didn't load, like a new file, starts out empty
+                if paths.get(&player.path).is_none() {
+                    paths.set_untracked(&player.path, CameraPath::default());
+                }
+                if let Some(path) = paths.get_mut(&player.path) {
+                    let time = path.keyframes.last().map_or(0., |keyframe| keyframe.time + player.key_interval);
+                    let keyframe = CameraKeyframe {
+                        time,
+                        position: transform.translation,
+                        target: transform.translation + transform.forward() * player.target_distance,
+                        fov: fov(&projection).unwrap_or(PerspectiveProjection::default().fov),
+                    };
+                    // Bezier paths get the two control points too, a third of the way apart, to edit in the file
+                    if let (PathInterpolation::Bezier, Some(previous)) = (path.interpolation, path.keyframes.last().copied()) {
+                        for t in [1. / 3., 2. / 3.] {
+                            path.keyframes.push(CameraKeyframe {
+                                time: previous.time + (time - previous.time) * t,
+                                position: previous.position.lerp(keyframe.position, t),
+                                target: previous.target.lerp(keyframe.target, t),
+                                fov: previous.fov + (keyframe.fov - previous.fov) * t,
+                            });
+                        }
+                    }
+                    path.keyframes.push(keyframe);
+                    info!("Camera keyframe {} at {:.1}s", path.keyframes.len(), time);
+                }
+            }
+            if kb_input.just_pressed(KeyCode::Back) {
+                if let Some(path) = paths.get_mut(&player.path) {
+                    // With its control points on a Bezier path
+                    let keep = match path.interpolation {
+                        PathInterpolation::CatmullRom => path.keyframes.len().saturating_sub(1),
+                        PathInterpolation::Bezier => path.keyframes.len().saturating_sub(3),
+                    };
+                    path.keyframes.truncate(keep);
+                }
+            }
+        }
+        if kb_input.just_pressed(KeyCode::F5) {
+            if let Some(path) = paths.get(&player.path) {
+                match save(&asset_server, &player.path, path) {
+                    Ok(file) => info!("Saved camera path to {}", file.display()),
+                    Err(error) => warn!("Couldn't save camera path: {}", error),
+                }
+            }
+        }
+
+        // Playback
+        if kb_input.just_pressed(KeyCode::P) {
+            if player.playing {
+                player.stop(&mut projection, rig.as_deref_mut());
+            } else if paths.get(&player.path).map_or(false, |path| !path.keyframes.is_empty()) {
+                player.playing = true;
+                player.time = 0.;
+                player.rig_fov = fov(&projection);
+            }
+        }
+        if !player.playing {
+            continue;
+        }
+        player.time += time.delta_seconds() * player.speed;
+        let (keyframe, finished) = match paths.get(&player.path) {
+            Some(path) => (path.sample(player.time), !path.looping && player.time >= path.duration()),
+            None => (None, true),
+        };
+        if let Some(keyframe) = keyframe {
+            *transform = Transform::from_translation(keyframe.position).looking_at(keyframe.target, Vec3::Y);
+            set_fov(&mut projection, keyframe.fov);
+        }
+        if finished {
+            player.stop(&mut projection, rig.as_deref_mut());
+        }
+    }
+}
+
+fn camera_path_debug_draw(
+    paths: Res<Assets<CameraPath>>,
+    mut lines: ResMut<DebugLines>,
+    players_query: Query<&CameraPathPlayer>,
+){
+    for player in players_query.iter() {
+        if !player.debug || player.playing {
+            continue;
+        }
+        let path = match paths.get(&player.path) {
+            Some(path) => path,
+            None => continue,
+        };
+        // Keyframes, with a short line the way they look
+        for keyframe in path.keyframes.iter() {
+            let look = (keyframe.target - keyframe.position).normalize_or_zero();
+            lines.line_colored(keyframe.position, keyframe.position + look, 0., Color::ORANGE);
+        }
+        let start = match path.keyframes.first() {
+            Some(keyframe) => keyframe.time,
+            None => continue,
+        };
+        let steps = ((path.duration() - start) / DEBUG_STEP).ceil() as usize;
+        let mut from = path.sample(start);
+        for step in 1..=steps {
+            let to = path.sample((start + step as f32 * DEBUG_STEP).min(path.duration()));
+            if let (Some(from), Some(to)) = (from, to) {
+                lines.line_colored(from.position, to.position, 0., Color::YELLOW);
+            }
+            from = to;
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use std::f32::consts::{PI, TAU};
+use bevy::{
+    ecs::schedule::SystemLabelId,
+    prelude::*,
+};
+use bevy_prototype_debug_lines::*;
+use crate::mouse_look::{FollowDistance, MouseLookInput, MouseLookSettings};
+
+// One camera rig for all the examples. A CameraRig on a camera moves it in one of its modes:
+// flying around freely, orbiting or following the entity marked CameraTarget, looking down on it
+// from above, or watching it from fixed shots. Tab or Select on a gamepad cycles through the rig's
+// modes, the camera blends over from wherever it was. Each mode has its own settings in the rig.
+//
+// Arrows, the right stick and mouse look rotate the camera in every mode but fixed shots.
+// Free fly moves with WASD, E and Q, or the left stick and North and South.
+
+#[derive(Default)]
+pub struct CameraRigPlugin {
+    after: Option<SystemLabelId>,
+    label: Option<SystemLabelId>,
+}
+
+impl CameraRigPlugin {
+    // Update the rig after this, usually whatever moves the target
+    pub fn after(mut self, label: impl SystemLabel) -> Self {
+        self.after = Some(label.as_label());
+        self
+    }
+
+    // Give the rig update the example's own label, to order other systems around it
+    pub fn label(mut self, label: impl SystemLabel) -> Self {
+        self.label = Some(label.as_label());
+        self
+    }
+}
+
+impl Plugin for CameraRigPlugin{
+    fn build(&self, app: &mut App) {
+        let mut update = camera_rig_update.into_descriptor();
+        if let Some(after) = self.after {
+            update = update.after(after);
+        }
+        if let Some(label) = self.label {
+            update = update.label(label);
+        }
+        app.register_type::<CameraRig>()
+        .add_system(update);
+    }
+}
+
+const GAMEPAD_DEADZONE: f32 = 0.1;
+const GAMEPAD_AXIS_SENSITIVITY: f32 = 5.5;
+
+// What orbit, follow, top-down and fixed shots look at. Without one the rig can only fly.
+#[derive(Component, Default)]
+pub struct CameraTarget {
+    pub offset: Vec3, // Point looked at, in the target's space
+}
+
+#[derive(Reflect, FromReflect, Clone, Copy, PartialEq, Debug)]
+pub enum CameraMode {
+    FreeFly,
+    Orbit,
+    Follow,
+    TopDown,
+    Fixed,
+}
+
+#[derive(Reflect)]
+pub struct FreeFlySettings {
+    pub rotation_speed: f32, // Radians per second at full input
+    pub acceleration: f32,
+    pub friction: f32,       // Fraction of speed remaining after one second
+    pub max_speed: f32,
+}
+impl Default for FreeFlySettings
+{
+    fn default() -> Self {
+        Self {
+            rotation_speed: 1.5,
+            acceleration: 50.,
+            friction: 0.05,
+            max_speed: 30.,
+        }
+    }
+}
+
+#[derive(Reflect)]
+pub struct OrbitSettings {
+    pub distance: FollowDistance,
+    pub rotation_speed: f32,
+    pub pitch_min: f32,
+    pub pitch_max: f32,
+}
+impl Default for OrbitSettings
+{
+    fn default() -> Self {
+        Self {
+            distance: FollowDistance::default(),
+            rotation_speed: 1.,
+            pitch_min: -1.,
+            pitch_max: 0.1,
+        }
+    }
+}
+
+// Orbit that swings in behind the direction of travel when there has been no manual look input
+// for a while. Manual input always wins and restarts the delay. C or the right thumb toggles it.
+#[derive(Reflect)]
+pub struct FollowSettings {
+    pub distance: FollowDistance,
+    pub rotation_speed: f32,
+    pub pitch_min: f32,
+    pub pitch_max: f32,
+    pub auto_follow: bool,
+    pub delay: f32,             // Seconds without look input before auto follow kicks in
+    pub min_speed: f32,         // Below this target speed the yaw is left alone
+    pub yaw_smoothing: f32,     // Fraction of yaw error remaining after one second
+    pub look_ahead: f32,        // Seconds of target velocity to look ahead of the target
+    pub look_ahead_smoothing: f32,
+    pub vertical_deadzone: f32, // Target can move this far up/down before the camera follows
+    pub vertical_smoothing: f32,
+    idle_time: f32,
+    look_ahead_offset: Vec3,
+    focus_height: Option<f32>,
+}
+impl Default for FollowSettings
+{
+    fn default() -> Self {
+        Self {
+            distance: FollowDistance::default(),
+            rotation_speed: 1.,
+            pitch_min: -1.,
+            pitch_max: 0.1,
+            auto_follow: true,
+            delay: 1.5,
+            min_speed: 0.5,
+            yaw_smoothing: 0.3,
+            look_ahead: 0.3,
+            look_ahead_smoothing: 0.1,
+            vertical_deadzone: 0.5,
+            vertical_smoothing: 0.05,
+            idle_time: 0.,
+            look_ahead_offset: Vec3::ZERO,
+            focus_height: None,
+        }
+    }
+}
+
+#[derive(Reflect)]
+pub struct TopDownSettings {
+    pub distance: FollowDistance,
+    pub rotation_speed: f32, // Yaw only, the pitch stays put
+    pub pitch: f32,
+}
+impl Default for TopDownSettings
+{
+    fn default() -> Self {
+        Self {
+            distance: FollowDistance {distance: 15., min: 5., max: 40.},
+            rotation_speed: 1.,
+            pitch: -1.3,
+        }
+    }
+}
+
+// Cameras placed around the level, the one closest to the target watches it.
+// Without any the camera stays where it was when the mode was entered.
+#[derive(Reflect, Default)]
+pub struct FixedSettings {
+    pub shots: Vec<Vec3>,
+    shot: Option<usize>,
+}
+
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct CameraRig {
+    pub mode: CameraMode,
+    pub modes: Vec<CameraMode>,   // Cycled through with Tab
+    pub transition_duration: f32, // Seconds to blend into a new mode
+    pub yaw: f32,                 // 0 looks along -Z
+    pub pitch: f32,               // Up is positive
+    pub free_fly: FreeFlySettings,
+    pub orbit: OrbitSettings,
+    pub follow: FollowSettings,
+    pub top_down: TopDownSettings,
+    pub fixed: FixedSettings,
+    pub debug: bool,              // Draw the rotation input in front of the camera
+    entered: Option<CameraMode>,
+    resume: bool,
+    transition_from: Transform,
+    transition_time: f32,
+    fly_position: Vec3,
+    fly_velocity: Vec3,
+    fixed_position: Vec3,
+    last_target: Option<Vec3>,
+}
+impl Default for CameraRig
+{
+    fn default() -> Self {
+        Self::new(vec![CameraMode::Follow, CameraMode::Orbit, CameraMode::TopDown, CameraMode::Fixed, CameraMode::FreeFly])
+    }
+}
+
+impl CameraRig {
+    // Starts in the first of the modes
+    pub fn new(modes: Vec<CameraMode>) -> Self {
+        Self {
+            mode: modes.first().copied().unwrap_or(CameraMode::FreeFly),
+            modes,
+            transition_duration: 0.75,
+            yaw: 0.,
+            pitch: 0.,
+            free_fly: FreeFlySettings::default(),
+            orbit: OrbitSettings::default(),
+            follow: FollowSettings::default(),
+            top_down: TopDownSettings::default(),
+            fixed: FixedSettings::default(),
+            debug: true,
+            entered: None,
+            resume: false,
+            transition_from: Transform::IDENTITY,
+            transition_time: 0.,
+            fly_position: Vec3::ZERO,
+            fly_velocity: Vec3::ZERO,
+            fixed_position: Vec3::ZERO,
+            last_target: None,
+        }
+    }
+
+    pub fn with_fixed_shots(mut self, shots: Vec<Vec3>) -> Self {
+        self.fixed.shots = shots;
+        self
+    }
+
+    pub fn next_mode(&mut self) {
+        let next = self.modes.iter().position(|mode| *mode == self.mode).map_or(0, |i| i + 1);
+        if let Some(mode) = self.modes.get(next % self.modes.len().max(1)) {
+            self.mode = *mode;
+        }
+    }
+
+    // Blend back in from wherever something else, like a camera path, left the camera
+    pub fn resume(&mut self) {
+        self.resume = true;
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(self.pitch)
+    }
+
+    // Pick up from where the camera is, blending over unless it's the first mode
+    fn enter(&mut self, mode: CameraMode, transform: &Transform) {
+        let forward = transform.forward();
+        self.yaw = yaw_of(forward);
+        self.pitch = forward.y.clamp(-1., 1.).asin();
+        self.fly_position = transform.translation;
+        self.fly_velocity = Vec3::ZERO;
+        self.fixed_position = transform.translation;
+        self.fixed.shot = None;
+        self.follow.idle_time = 0.;
+        self.follow.focus_height = None;
+        if self.entered.is_some() {
+            self.transition_from = *transform;
+            self.transition_time = 0.;
+        } else {
+            self.transition_time = self.transition_duration;
+        }
+        self.entered = Some(mode);
+        self.resume = false;
+    }
+}
+
+// Yaw that looks along a direction, 0 along -Z
+fn yaw_of(direction: Vec3) -> f32 {
+    (-direction.x).atan2(-direction.z)
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    angle.rem_euclid(TAU)
+}
+
+// Shortest way round from one angle to another
+fn angle_difference(from: f32, to: f32) -> f32 {
+    let diff = (to - from).rem_euclid(TAU);
+    if diff > PI {diff - TAU} else {diff}
+}
+
+fn stick_curve(value: f32) -> f32 {
+    value.abs().powf(GAMEPAD_AXIS_SENSITIVITY) * value.signum()
+}
+
+pub fn camera_rig_update(
+    time: Res<Time>,
+    kb_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    look_settings: Res<MouseLookSettings>,
+    look_input: Res<MouseLookInput>,
+    mut lines: ResMut<DebugLines>,
+    mut rigs_query: Query<(&mut Transform, &mut CameraRig), Without<CameraTarget>>,
+    target_query: Query<(&Transform, &CameraTarget), Without<CameraRig>>,
+){
+    let dt = time.delta_seconds();
+    if dt <= 0. {
+        return;
+    }
+    let target = target_query.iter().next().map(|(transform, target)| transform.transform_point(target.offset));
+
+    // Construct input vectors from keyboard presses
+    let mut rotate_input = Vec2::new(
+        if kb_input.pressed(KeyCode::Left) {-1.} else if kb_input.pressed(KeyCode::Right) {1.} else {0.},
+        if kb_input.pressed(KeyCode::Up) {1.} else if kb_input.pressed(KeyCode::Down) {-1.} else {0.});
+    let mut fly_input = Vec3::new(
+        if kb_input.pressed(KeyCode::A) {-1.} else if kb_input.pressed(KeyCode::D) {1.} else {0.},
+        if kb_input.pressed(KeyCode::E) {1.} else if kb_input.pressed(KeyCode::Q) {-1.} else {0.},
+        if kb_input.pressed(KeyCode::W) {-1.} else if kb_input.pressed(KeyCode::S) {1.} else {0.});
+    let mut cycle_mode = kb_input.just_pressed(KeyCode::Tab);
+    let mut toggle_auto_follow = kb_input.just_pressed(KeyCode::C);
+
+    // if we have a gamepad, let it override input
+    for gamepad in gamepads.iter() {
+        let rotate_input_raw = Vec2::new(
+            gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickX)).unwrap(),
+            gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY)).unwrap());
+        let fly_input_raw = Vec2::new(
+            gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap(),
+            gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap());
+        if rotate_input_raw.length_squared() > GAMEPAD_DEADZONE * GAMEPAD_DEADZONE {
+            rotate_input = Vec2::new(stick_curve(rotate_input_raw.x), stick_curve(rotate_input_raw.y));
+        }
+        if fly_input_raw.length_squared() > GAMEPAD_DEADZONE * GAMEPAD_DEADZONE {
+            fly_input.x = stick_curve(fly_input_raw.x);
+            fly_input.z = -stick_curve(fly_input_raw.y);
+        }
+        if gamepad_buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::North)) {
+            fly_input.y = 1.;
+        } else if gamepad_buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::South)) {
+            fly_input.y = -1.;
+        }
+        cycle_mode |= gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Select));
+        toggle_auto_follow |= gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::RightThumb));
+    }
+    let looking = rotate_input.length_squared() > 0. || look_input.rotation != Vec2::ZERO;
+
+    for (mut transform, mut rig) in rigs_query.iter_mut() {
+        let rig = &mut *rig;
+        if cycle_mode {
+            rig.next_mode();
+        }
+        if toggle_auto_follow {
+            rig.follow.auto_follow = !rig.follow.auto_follow;
+        }
+
+        // Target velocity on the ground plane, for following
+        let travel = match (target, rig.last_target) {
+            (Some(target), Some(last_target)) => (target - last_target) / dt,
+            _ => Vec3::ZERO,
+        };
+        let travel = Vec3::new(travel.x, 0., travel.z);
+        rig.last_target = target;
+
+        // Only free fly works without a target
+        let (mode, center) = match target {
+            Some(target) => (rig.mode, target),
+            None => (CameraMode::FreeFly, Vec3::ZERO),
+        };
+        if rig.entered != Some(mode) || rig.resume {
+            rig.enter(mode, &transform);
+        }
+
+        // Rotate
+        let (rotation_speed, pitch_min, pitch_max) = match mode {
+            CameraMode::FreeFly => (rig.free_fly.rotation_speed, -1., 1.),
+            CameraMode::Orbit => (rig.orbit.rotation_speed, rig.orbit.pitch_min, rig.orbit.pitch_max),
+            CameraMode::Follow => (rig.follow.rotation_speed, rig.follow.pitch_min, rig.follow.pitch_max),
+            CameraMode::TopDown => (rig.top_down.rotation_speed, rig.pitch, rig.pitch),
+            CameraMode::Fixed => (0., rig.pitch, rig.pitch),
+        };
+        if mode != CameraMode::Fixed {
+            rig.yaw = wrap_angle(rig.yaw - rotate_input.x * rotation_speed * dt + look_input.rotation.y);
+            rig.pitch = (rig.pitch + rotate_input.y * rotation_speed * dt + look_input.rotation.x).clamp(pitch_min, pitch_max);
+        }
+
+        let camera = match mode {
+            CameraMode::FreeFly => {
+                // Transform input to world space
+                let rotation = rig.rotation();
+                let fly_direction = rotation * Vec3::new(fly_input.x, 0., fly_input.z) + Vec3::Y * fly_input.y;
+                let settings = &rig.free_fly;
+                // Accelerate, friction, clamp max speed
+                let mut velocity = rig.fly_velocity + settings.acceleration * fly_direction * dt;
+                velocity = velocity.lerp(Vec3::ZERO, 1. - settings.friction.powf(dt));
+                rig.fly_velocity = velocity.clamp_length_max(settings.max_speed);
+                rig.fly_position += rig.fly_velocity * dt;
+                Transform::from_translation(rig.fly_position).with_rotation(rotation)
+            }
+            CameraMode::Orbit => {
+                rig.orbit.distance.zoom(look_input.zoom, look_settings.zoom_sensitivity);
+                let offset = rig.rotation() * Vec3::new(0., 0., rig.orbit.distance.distance);
+                Transform::from_translation(center + offset).looking_at(center, Vec3::Y)
+            }
+            CameraMode::Follow => {
+                let follow = &mut rig.follow;
+                follow.distance.zoom(look_input.zoom, look_settings.zoom_sensitivity);
+
+                // Swing in behind the direction of travel after a while without look input
+                follow.idle_time = if looking {0.} else {follow.idle_time + dt};
+                if follow.auto_follow && follow.idle_time > follow.delay && travel.length() > follow.min_speed {
+                    let yaw_t = 1. - follow.yaw_smoothing.powf(dt);
+                    rig.yaw = wrap_angle(rig.yaw + angle_difference(rig.yaw, yaw_of(travel)) * yaw_t);
+                }
+
+                // Look ahead of the target along its velocity
+                let look_ahead_target = if follow.auto_follow {travel * follow.look_ahead} else {Vec3::ZERO};
+                let look_ahead_t = 1. - follow.look_ahead_smoothing.powf(dt);
+                follow.look_ahead_offset = follow.look_ahead_offset.lerp(look_ahead_target, look_ahead_t);
+
+                // Only follow vertical movement once the target leaves the deadzone
+                let mut focus_height = follow.focus_height.unwrap_or(center.y);
+                let height_diff = center.y - focus_height;
+                if height_diff.abs() > follow.vertical_deadzone {
+                    let target_height = center.y - height_diff.signum() * follow.vertical_deadzone;
+                    focus_height += (target_height - focus_height) * (1. - follow.vertical_smoothing.powf(dt));
+                }
+                follow.focus_height = Some(focus_height);
+
+                let mut focus = center + follow.look_ahead_offset;
+                focus.y = focus_height;
+                let offset = rig.rotation() * Vec3::new(0., 0., rig.follow.distance.distance);
+                Transform::from_translation(focus + offset).looking_at(focus, Vec3::Y)
+            }
+            CameraMode::TopDown => {
+                let top_down = &mut rig.top_down;
+                top_down.distance.zoom(look_input.zoom, look_settings.zoom_sensitivity);
+                let rotation = Quat::from_rotation_y(rig.yaw) * Quat::from_rotation_x(top_down.pitch);
+                let offset = rotation * Vec3::new(0., 0., top_down.distance.distance);
+                Transform::from_translation(center + offset).looking_at(center, Vec3::Y)
+            }
+            CameraMode::Fixed => {
+                let closest = rig.fixed.shots.iter().enumerate()
+                    .min_by(|(_, a), (_, b)| a.distance_squared(center).total_cmp(&b.distance_squared(center)))
+                    .map(|(i, shot)| (i, *shot));
+                // Cut between shots, like a film
+                if let Some((shot, _)) = closest {
+                    if rig.fixed.shot.map_or(false, |current| current != shot) {
+                        rig.transition_time = rig.transition_duration;
+                    }
+                    rig.fixed.shot = Some(shot);
+                }
+                let position = closest.map_or(rig.fixed_position, |(_, position)| position);
+                let camera = Transform::from_translation(position).looking_at(center, Vec3::Y);
+                // Keep the yaw in step, things that move relative to the camera use it
+                rig.yaw = wrap_angle(yaw_of(camera.forward()));
+                camera
+            }
+        };
+
+        // Blend over from where the camera was when the mode changed, easing in and out
+        let mut camera = camera;
+        if rig.transition_time < rig.transition_duration {
+            rig.transition_time += dt;
+            let t = (rig.transition_time / rig.transition_duration).min(1.);
+            let t = t * t * (3. - 2. * t);
+            camera.translation = rig.transition_from.translation.lerp(camera.translation, t);
+            camera.rotation = rig.transition_from.rotation.slerp(camera.rotation, t);
+        }
+        *transform = camera;
+
+        if rig.debug {
+            let line_start_2d = Vec3::new(0., 0., -1.0);
+            let line_end_2d = line_start_2d + rotate_input.extend(0.).normalize_or_zero() * 0.2;
+            lines.line_gradient(transform.transform_point(line_start_2d), transform.transform_point(line_end_2d), 0.,
+            Color::RED, Color::LIME_GREEN);
+        }
+    }
+}
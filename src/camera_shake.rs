@@ -0,0 +1,199 @@
+use bevy::{
+    ecs::{schedule::SystemLabelId, system::AsSystemLabel},
+    prelude::*,
+};
+
+// Trauma based camera shake. Things that happen send CameraTrauma events, a CameraShake on the camera
+// adds them up to a trauma between 0 and 1 that decays over time, and shakes as much as trauma squared,
+// so small knocks barely show and big ones really do. The shake is smooth noise on translation and
+// rotation, put on top of wherever the camera systems left the camera and taken off again before
+// the next frame, so they never see it. X sets off a test shake.
+//
+// Some players can't stand it, CameraShakeSettings turns it off or down.
+
+#[derive(Default)]
+pub struct CameraShakePlugin {
+    after: Vec<SystemLabelId>,
+}
+
+impl CameraShakePlugin {
+    // Shake after whatever moves the camera
+    pub fn after<Marker>(mut self, label: impl AsSystemLabel<Marker>) -> Self {
+        self.after.push(label.as_system_label());
+        self
+    }
+}
+
+impl Plugin for CameraShakePlugin{
+    fn build(&self, app: &mut App) {
+        let mut update = camera_shake_update.into_descriptor();
+        for after in self.after.iter() {
+            update = update.after(*after);
+        }
+        app.init_resource::<CameraShakeSettings>()
+        .add_event::<CameraTrauma>()
+        .register_type::<CameraShakeSettings>()
+        .register_type::<CameraShake>()
+        .add_system_to_stage(CoreStage::PreUpdate, camera_shake_restore)
+        .add_system(camera_shake_test.before(camera_shake_update))
+        .add_system(update);
+    }
+}
+
+const TEST_TRAUMA: f32 = 0.6;
+
+// Accessibility, for everyone's cameras
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct CameraShakeSettings {
+    pub enabled: bool,
+    pub intensity: f32, // Scales the shake, not the trauma
+}
+impl Default for CameraShakeSettings
+{
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.,
+        }
+    }
+}
+
+// Trauma to add to the shaking cameras, from landings, collisions, explosions and the like
+pub struct CameraTrauma {
+    pub amount: f32,
+    pub origin: Option<Vec3>, // Where it happened, cameras further away feel less of it
+    pub radius: f32,          // Not felt at all past this far from the origin
+}
+
+impl CameraTrauma {
+    // Felt the same everywhere
+    pub fn new(amount: f32) -> Self {
+        Self {amount, origin: None, radius: 0.}
+    }
+
+    pub fn at(amount: f32, origin: Vec3, radius: f32) -> Self {
+        Self {amount, origin: Some(origin), radius}
+    }
+
+    pub fn amount_at(&self, position: Vec3) -> f32 {
+        match self.origin {
+            Some(origin) if self.radius > 0. => self.amount * (1. - origin.distance(position) / self.radius).max(0.),
+            Some(_) => 0.,
+            None => self.amount,
+        }
+    }
+}
+
+#[derive(Reflect, Component)]
+#[reflect(Component)]
+pub struct CameraShake {
+    pub trauma: f32,           // 0 to 1
+    pub decay: f32,            // Trauma lost per second
+    pub max_translation: Vec3, // At full trauma, in camera space
+    pub max_rotation: Vec3,    // Pitch, yaw and roll at full trauma, radians
+    pub frequency: f32,        // Noise per second, higher is more violent
+    time: f32,
+    offset: Transform,         // Applied this frame
+}
+impl Default for CameraShake
+{
+    fn default() -> Self {
+        Self {
+            trauma: 0.,
+            decay: 0.8,
+            max_translation: Vec3::new(0.3, 0.3, 0.1),
+            max_rotation: Vec3::new(0.05, 0.05, 0.1),
+            frequency: 15.,
+            time: 0.,
+            offset: Transform::IDENTITY,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+}
+
+// Random gradient in -1 to 1 for each integer, the same for the same seed
+fn hash(seed: u32, i: i32) -> f32 {
+    let mut x = (i as u32).wrapping_mul(0x9E37_79B1) ^ seed.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2C1B_3C6D);
+    x ^= x >> 12;
+    x = x.wrapping_mul(0x297A_2D39);
+    x ^= x >> 15;
+    x as f32 / u32::MAX as f32 * 2. - 1.
+}
+
+// Smooth 1d gradient noise, roughly -1 to 1
+pub fn noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let from = hash(seed, i as i32) * f;
+    let to = hash(seed, i as i32 + 1) * (f - 1.);
+    let s = f * f * f * (f * (f * 6. - 15.) + 10.);
+    (from + (to - from) * s) * 2.
+}
+
+// Take last frame's shake off, before anything moves the camera
+fn camera_shake_restore(
+    mut cameras_query: Query<(&mut Transform, &mut CameraShake)>,
+){
+    for (mut transform, mut shake) in cameras_query.iter_mut() {
+        if shake.offset == Transform::IDENTITY {
+            continue;
+        }
+        transform.rotation = transform.rotation * shake.offset.rotation.inverse();
+        transform.translation -= transform.rotation * shake.offset.translation;
+        shake.offset = Transform::IDENTITY;
+    }
+}
+
+fn camera_shake_test(
+    kb_input: Res<Input<KeyCode>>,
+    mut trauma_events: EventWriter<CameraTrauma>,
+){
+    if kb_input.just_pressed(KeyCode::X) {
+        trauma_events.send(CameraTrauma::new(TEST_TRAUMA));
+    }
+}
+
+pub fn camera_shake_update(
+    time: Res<Time>,
+    settings: Res<CameraShakeSettings>,
+    mut trauma_events: EventReader<CameraTrauma>,
+    mut cameras_query: Query<(&mut Transform, &mut CameraShake)>,
+){
+    let events: Vec<&CameraTrauma> = trauma_events.iter().collect();
+    if !settings.enabled {
+        return;
+    }
+    let dt = time.delta_seconds();
+    for (mut transform, mut shake) in cameras_query.iter_mut() {
+        for event in events.iter() {
+            shake.add_trauma(event.amount_at(transform.translation));
+        }
+        shake.trauma = (shake.trauma - shake.decay * dt).max(0.);
+        shake.time += dt;
+        if shake.trauma <= 0. {
+            continue;
+        }
+
+        // A different noise channel for each axis
+        let amount = shake.trauma * shake.trauma * settings.intensity;
+        let t = shake.time * shake.frequency;
+        let channel = |seed: u32| noise(seed, t) * amount;
+        let translation = shake.max_translation * Vec3::new(channel(0), channel(1), channel(2));
+        let rotation = shake.max_rotation * Vec3::new(channel(3), channel(4), channel(5));
+        shake.offset = Transform::from_translation(translation)
+            .with_rotation(Quat::from_euler(EulerRot::YXZ, rotation.y, rotation.x, rotation.z));
+
+        // In camera space
+        let offset = transform.rotation * shake.offset.translation;
+        transform.translation += offset;
+        transform.rotation = transform.rotation * shake.offset.rotation;
+    }
+}
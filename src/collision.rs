@@ -0,0 +1,589 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues, PrimitiveTopology},
+};
+
+// Capsule collision and a kinematic character mover against static geometry.
+// Plain functions on a list of colliders, no ECS, so it runs the same in a game and headless.
+//
+// Collision is resolved rather than swept: the motion is split into steps no longer than half
+// the capsule radius, after each step the capsule is pushed out of whatever it overlaps and the
+// rest of the motion slides along the contacts. Good enough for walking speeds.
+
+// Upright capsule standing on its origin
+#[derive(Clone, Copy, Debug)]
+pub struct Capsule {
+    pub radius: f32,
+    pub height: f32, // Total, including the rounded ends
+}
+
+impl Capsule {
+    // The capsule's core segment, bottom and top sphere centers
+    pub fn segment(&self, position: Vec3) -> (Vec3, Vec3) {
+        let half_core = ((self.height - 2. * self.radius) * 0.5).max(0.);
+        let center = position + Vec3::Y * self.height * 0.5;
+        (center - Vec3::Y * half_core, center + Vec3::Y * half_core)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Collider {
+    // Oriented box
+    Box {
+        center: Vec3,
+        rotation: Quat,
+        half_extents: Vec3,
+    },
+    Triangle([Vec3; 3]),
+}
+
+// Closest point on a collider to a point, and the direction from there to the point.
+// distance is negative when the point is inside a box.
+#[derive(Clone, Copy, Debug)]
+pub struct PointContact {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+fn closest_on_segment(a: Vec3, b: Vec3, point: Vec3) -> Vec3 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared <= 0. {
+        return a;
+    }
+    a + ab * ((point - a).dot(ab) / length_squared).clamp(0., 1.)
+}
+
+// From Real-Time Collision Detection, Ericson, 5.1.5
+fn closest_on_triangle(point: Vec3, [a, b, c]: [Vec3; 3]) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = point - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0. && d2 <= 0. {
+        return a;
+    }
+    let bp = point - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0. && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0. && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && (d4 - d3) >= 0. && (d5 - d6) >= 0. {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1. / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+impl Collider {
+    // Box from a world transform and a local bounding box, like a mesh's Aabb
+    pub fn from_aabb(transform: &GlobalTransform, center: Vec3, half_extents: Vec3) -> Self {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        Collider::Box {
+            center: translation + rotation * (scale * center),
+            rotation,
+            half_extents: half_extents * scale.abs(),
+        }
+    }
+
+    // Triangles of a triangle list mesh, in world space
+    pub fn from_mesh(mesh: &Mesh, transform: &GlobalTransform) -> Vec<Self> {
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => return Vec::new(),
+        };
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return Vec::new();
+        }
+        let world: Vec<Vec3> = positions.iter().map(|position| transform.transform_point(Vec3::from(*position))).collect();
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|index| *index as usize).collect(),
+            Some(Indices::U32(indices)) => indices.iter().map(|index| *index as usize).collect(),
+            None => (0..world.len()).collect(),
+        };
+        indices.chunks_exact(3)
+            .map(|triangle| Collider::Triangle([world[triangle[0]], world[triangle[1]], world[triangle[2]]]))
+            .collect()
+    }
+
+    // Center and radius of a sphere around the collider, to skip far away ones
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        match self {
+            Collider::Box {center, half_extents, ..} => (*center, half_extents.length()),
+            Collider::Triangle([a, b, c]) => {
+                let center = (*a + *b + *c) / 3.;
+                (center, (*a - center).length().max((*b - center).length()).max((*c - center).length()))
+            }
+        }
+    }
+
+    pub fn contact(&self, point: Vec3) -> PointContact {
+        match self {
+            Collider::Box {center, rotation, half_extents} => {
+                let local = rotation.inverse() * (point - *center);
+                let clamped = local.clamp(-*half_extents, *half_extents);
+                if clamped != local {
+                    let offset = local - clamped;
+                    let distance = offset.length();
+                    return PointContact {
+                        point: *center + *rotation * clamped,
+                        normal: *rotation * (offset / distance),
+                        distance,
+                    };
+                }
+                // Inside, out through the nearest face
+                let depths = *half_extents - local.abs();
+                let axis = if depths.x < depths.y && depths.x < depths.z {0} else if depths.y < depths.z {1} else {2};
+                let mut normal = Vec3::ZERO;
+                normal[axis] = if local[axis] < 0. {-1.} else {1.};
+                let mut surface = local;
+                surface[axis] = half_extents[axis] * normal[axis];
+                PointContact {
+                    point: *center + *rotation * surface,
+                    normal: *rotation * normal,
+                    distance: -depths[axis],
+                }
+            }
+            Collider::Triangle(triangle) => {
+                let closest = closest_on_triangle(point, *triangle);
+                let offset = point - closest;
+                let distance = offset.length();
+                let normal = if distance > 1e-6 {
+                    offset / distance
+                } else {
+                    // On the triangle, use its face normal
+                    let [a, b, c] = *triangle;
+                    (b - a).cross(c - a).normalize_or_zero()
+                };
+                PointContact {point: closest, normal, distance}
+            }
+        }
+    }
+
+    // World space bounding box, min and max corners
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        match self {
+            Collider::Box {center, rotation, half_extents} => {
+                let rotation = Mat3::from_quat(*rotation);
+                let extents = rotation.x_axis.abs() * half_extents.x
+                    + rotation.y_axis.abs() * half_extents.y
+                    + rotation.z_axis.abs() * half_extents.z;
+                (*center - extents, *center + extents)
+            }
+            Collider::Triangle([a, b, c]) => (a.min(*b).min(*c), a.max(*b).max(*c)),
+        }
+    }
+
+    // First hit of a ray (direction normalized) within max_distance, distance and surface normal.
+    // Rays starting inside a box don't hit it.
+    pub fn ray_cast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<(f32, Vec3)> {
+        match self {
+            Collider::Box {center, rotation, half_extents} => {
+                // Slab test in the box's space
+                let local_origin = rotation.inverse() * (origin - *center);
+                let local_direction = rotation.inverse() * direction;
+                let mut t_enter = 0.;
+                let mut t_exit = max_distance;
+                let mut normal = Vec3::ZERO;
+                for axis in 0..3 {
+                    if local_direction[axis].abs() < 1e-8 {
+                        if local_origin[axis].abs() > half_extents[axis] {
+                            return None;
+                        }
+                        continue;
+                    }
+                    let inverse = 1. / local_direction[axis];
+                    let mut t0 = (-half_extents[axis] - local_origin[axis]) * inverse;
+                    let mut t1 = (half_extents[axis] - local_origin[axis]) * inverse;
+                    let mut side = -1.;
+                    if t0 > t1 {
+                        std::mem::swap(&mut t0, &mut t1);
+                        side = 1.;
+                    }
+                    if t0 > t_enter {
+                        t_enter = t0;
+                        normal = Vec3::ZERO;
+                        normal[axis] = side;
+                    }
+                    t_exit = t_exit.min(t1);
+                    if t_enter > t_exit {
+                        return None;
+                    }
+                }
+                if normal == Vec3::ZERO {
+                    return None;
+                }
+                Some((t_enter, *rotation * normal))
+            }
+            // Möller-Trumbore
+            Collider::Triangle([a, b, c]) => {
+                let ab = *b - *a;
+                let ac = *c - *a;
+                let p = direction.cross(ac);
+                let determinant = ab.dot(p);
+                if determinant.abs() < 1e-8 {
+                    return None;
+                }
+                let inverse = 1. / determinant;
+                let to_origin = origin - *a;
+                let u = to_origin.dot(p) * inverse;
+                if !(0. ..=1.).contains(&u) {
+                    return None;
+                }
+                let q = to_origin.cross(ab);
+                let v = direction.dot(q) * inverse;
+                if v < 0. || u + v > 1. {
+                    return None;
+                }
+                let t = ac.dot(q) * inverse;
+                if t < 0. || t > max_distance {
+                    return None;
+                }
+                let normal = ab.cross(ac).normalize_or_zero();
+                Some((t, if normal.dot(direction) > 0. {-normal} else {normal}))
+            }
+        }
+    }
+
+    // Closest approach between a segment and the collider, by alternating closest point
+    // projections (they converge for convex shapes)
+    pub fn segment_contact(&self, a: Vec3, b: Vec3) -> PointContact {
+        let mut on_segment = (a + b) * 0.5;
+        let mut contact = self.contact(on_segment);
+        for _ in 0..4 {
+            on_segment = closest_on_segment(a, b, contact.point);
+            contact = self.contact(on_segment);
+        }
+        contact
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+// Closest hit of a ray (direction normalized) within max_distance on any of the colliders
+pub fn ray_cast(colliders: &[Collider], origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+    colliders.iter()
+        .filter_map(|collider| collider.ray_cast(origin, direction, max_distance))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(distance, normal)| RayHit {point: origin + direction * distance, normal, distance})
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerSettings {
+    pub max_slope: f32,     // Steepest walkable ground in radians
+    pub step_height: f32,   // Ledges this high are stepped onto
+    pub snap_distance: f32, // Keep to the ground going down slopes and steps this deep
+    pub skin: f32,          // Gap kept to surfaces
+}
+impl Default for ControllerSettings
+{
+    fn default() -> Self {
+        Self {
+            max_slope: 0.8,
+            step_height: 0.25,
+            snap_distance: 0.3,
+            skin: 0.01,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MoveResult {
+    pub position: Vec3,
+    pub grounded: bool,
+    pub ground_normal: Vec3, // Up when not grounded
+    pub hit_ceiling: bool,
+}
+
+// Push the capsule out of the colliders. Steep surfaces only push sideways, so they can't be climbed.
+// Returns the corrected position and the normals of the contacts.
+fn depenetrate(colliders: &[Collider], capsule: &Capsule, settings: &ControllerSettings, mut position: Vec3) -> (Vec3, Vec<Vec3>) {
+    let min_ground_y = settings.max_slope.cos();
+    let mut normals = Vec::new();
+    for _ in 0..4 {
+        let mut moved = false;
+        for collider in colliders.iter() {
+            let (a, b) = capsule.segment(position);
+            let (sphere_center, sphere_radius) = collider.bounding_sphere();
+            let reach = capsule.radius + settings.skin + sphere_radius;
+            if (closest_on_segment(a, b, sphere_center) - sphere_center).length_squared() > reach * reach {
+                continue;
+            }
+            let contact = collider.segment_contact(a, b);
+            let depth = capsule.radius + settings.skin - contact.distance;
+            if depth <= 0. || contact.normal == Vec3::ZERO {
+                continue;
+            }
+            let mut normal = contact.normal;
+            if normal.y > 0. && normal.y < min_ground_y {
+                let sideways = Vec3::new(normal.x, 0., normal.z).normalize_or_zero();
+                if sideways != Vec3::ZERO {
+                    normal = sideways;
+                }
+            }
+            position += normal * depth;
+            normals.push(normal);
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+    (position, normals)
+}
+
+// Move and slide along whatever is hit. Returns the position and contact normals.
+fn slide(colliders: &[Collider], capsule: &Capsule, settings: &ControllerSettings, mut position: Vec3, mut motion: Vec3) -> (Vec3, Vec<Vec3>) {
+    let step_length = (capsule.radius * 0.5).max(0.01);
+    let steps = (motion.length() / step_length).ceil().max(1.) as usize;
+    let mut all_normals = Vec::new();
+    for step in 0..steps {
+        let remaining_steps = (steps - step) as f32;
+        let (resolved, normals) = depenetrate(colliders, capsule, settings, position + motion / remaining_steps);
+        motion -= motion / remaining_steps;
+        // Don't keep pushing into what we hit
+        for normal in normals.iter() {
+            let into = motion.dot(*normal);
+            if into < 0. {
+                motion -= *normal * into;
+            }
+        }
+        position = resolved;
+        all_normals.extend(normals);
+    }
+    (position, all_normals)
+}
+
+fn walkable_normal(normals: &[Vec3], settings: &ControllerSettings) -> Option<Vec3> {
+    let min_ground_y = settings.max_slope.cos();
+    normals.iter().copied()
+        .filter(|normal| normal.y >= min_ground_y)
+        .max_by(|a, b| a.y.partial_cmp(&b.y).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+// Ground under the capsule within distance, moving down onto it
+fn find_ground(colliders: &[Collider], capsule: &Capsule, settings: &ControllerSettings, position: Vec3, distance: f32) -> Option<(Vec3, Vec3)> {
+    let (landed, normals) = slide(colliders, capsule, settings, position, Vec3::NEG_Y * distance);
+    walkable_normal(&normals, settings).map(|normal| (landed, normal))
+}
+
+// Move a capsule at position by motion, colliding and sliding.
+// Steps up ledges up to step_height and, when it started on the ground and isn't moving up,
+// stays on the ground going down steps and slopes.
+pub fn move_character(
+    colliders: &[Collider],
+    capsule: &Capsule,
+    settings: &ControllerSettings,
+    position: Vec3,
+    motion: Vec3,
+    was_grounded: bool,
+) -> MoveResult {
+    let horizontal = Vec3::new(motion.x, 0., motion.z);
+    let vertical = Vec3::Y * motion.y;
+
+    // Horizontal, and if that gets blocked, again from a step higher
+    let (mut moved, _) = slide(colliders, capsule, settings, position, horizontal);
+    if was_grounded && horizontal.length() > 1e-4 {
+        let progress = (moved - position).dot(horizontal.normalize());
+        if progress < horizontal.length() * 0.9 {
+            let (raised, _) = slide(colliders, capsule, settings, position, Vec3::Y * settings.step_height);
+            let (stepped, _) = slide(colliders, capsule, settings, raised, horizontal);
+            if let Some((landed, _)) = find_ground(colliders, capsule, settings, stepped, (raised.y - position.y) + settings.skin) {
+                if (landed - position).dot(horizontal.normalize()) > progress + 1e-4 {
+                    moved = landed;
+                }
+            }
+        }
+    }
+
+    // Vertical
+    let (mut moved, normals) = slide(colliders, capsule, settings, moved, vertical);
+    let hit_ceiling = motion.y > 0. && normals.iter().any(|normal| normal.y < -0.5);
+    let mut ground = walkable_normal(&normals, settings);
+
+    // Stick to the ground going down
+    if ground.is_none() && was_grounded && motion.y <= 0. {
+        if let Some((landed, normal)) = find_ground(colliders, capsule, settings, moved, settings.snap_distance) {
+            moved = landed;
+            ground = Some(normal);
+        }
+    }
+    // Still touching the ground without having moved into it
+    if ground.is_none() && motion.y <= 0. {
+        let (_, normals) = depenetrate(colliders, capsule, settings, moved - Vec3::Y * settings.skin * 2.);
+        ground = walkable_normal(&normals, settings);
+    }
+
+    MoveResult {
+        position: moved,
+        grounded: ground.is_some(),
+        ground_normal: ground.unwrap_or(Vec3::Y),
+        hit_ceiling,
+    }
+}
+
+// Scene pieces for the tests, here and in navigation.rs
+#[cfg(test)]
+pub(crate) mod test_scenes {
+    use bevy::prelude::*;
+    use super::Collider;
+
+    pub fn cuboid(center: Vec3, half_extents: Vec3) -> Collider {
+        Collider::Box {center, rotation: Quat::IDENTITY, half_extents}
+    }
+
+    // Square, half_size from the origin to its edges, top at y = 0
+    pub fn floor(half_size: f32) -> Collider {
+        cuboid(Vec3::new(0., -0.5, 0.), Vec3::new(half_size, 0.5, half_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_scenes::{cuboid, floor};
+
+    const CAPSULE: Capsule = Capsule {radius: 0.3, height: 1.8};
+    // Where the capsule rests on the floor, kept the skin away
+    const REST_Y: f32 = 0.01;
+
+    const FLOOR_SIZE: f32 = 20.;
+
+    // Box rotated about X, its top face tilted up towards -Z
+    fn ramp(center: Vec3, half_extents: Vec3, degrees: f32) -> Collider {
+        Collider::Box {center, rotation: Quat::from_rotation_x(degrees.to_radians()), half_extents}
+    }
+
+    // Walk for a number of frames like the character controller does, with a little gravity
+    fn walk(colliders: &[Collider], mut position: Vec3, step: Vec3, frames: usize) -> MoveResult {
+        let settings = ControllerSettings::default();
+        let mut result = move_character(colliders, &CAPSULE, &settings, position, Vec3::NEG_Y * 0.02, true);
+        for _ in 0..frames {
+            position = result.position;
+            result = move_character(colliders, &CAPSULE, &settings, position, step + Vec3::NEG_Y * 0.02, result.grounded);
+        }
+        result
+    }
+
+    #[test]
+    fn stands_on_the_floor() {
+        let result = move_character(&[floor(FLOOR_SIZE)], &CAPSULE, &ControllerSettings::default(), Vec3::new(0., 0.05, 0.), Vec3::NEG_Y * 0.1, false);
+        assert!(result.grounded);
+        assert!((result.position.y - REST_Y).abs() < 1e-3, "{:?}", result.position);
+        assert!(result.ground_normal.abs_diff_eq(Vec3::Y, 1e-4));
+    }
+
+    #[test]
+    fn not_grounded_in_the_air() {
+        let result = move_character(&[floor(FLOOR_SIZE)], &CAPSULE, &ControllerSettings::default(), Vec3::new(0., 2., 0.), Vec3::NEG_Y * 0.1, false);
+        assert!(!result.grounded);
+        assert_eq!(result.ground_normal, Vec3::Y);
+        assert!((result.position.y - 1.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn slides_along_wall() {
+        // Face at z = -1.5
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(0., 1., -2.), Vec3::new(5., 1., 0.5))];
+        let settings = ControllerSettings::default();
+        let result = move_character(&colliders, &CAPSULE, &settings, Vec3::new(0., REST_Y, -1.), Vec3::new(0.5, -0.02, -0.5), true);
+        assert!((result.position.x - 0.5).abs() < 0.02, "{:?}", result.position);
+        assert!((result.position.z - (-1.5 + CAPSULE.radius + settings.skin)).abs() < 0.02, "{:?}", result.position);
+        assert!(result.grounded);
+    }
+
+    #[test]
+    fn steps_up_low_step() {
+        // Top at 0.2, under the step height of 0.25
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(0., 0.1, -1.), Vec3::new(2., 0.1, 0.3))];
+        let result = move_character(&colliders, &CAPSULE, &ControllerSettings::default(), Vec3::new(0., REST_Y, 0.), Vec3::new(0., -0.02, -1.), true);
+        assert!(result.grounded);
+        assert!((result.position.y - (0.2 + REST_Y)).abs() < 0.02, "{:?}", result.position);
+        assert!((result.position.z - -1.).abs() < 0.02, "{:?}", result.position);
+    }
+
+    #[test]
+    fn stopped_by_high_step() {
+        // Top at 0.4, over the step height, near face at z = -0.7
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(0., 0.2, -1.), Vec3::new(2., 0.2, 0.3))];
+        let result = walk(&colliders, Vec3::new(0., REST_Y, 0.), Vec3::new(0., 0., -0.1), 20);
+        assert!(result.grounded);
+        assert!(result.position.y < 0.05, "{:?}", result.position);
+        assert!(result.position.z > -0.7 + 0.2, "{:?}", result.position);
+    }
+
+    #[test]
+    fn walks_up_shallow_ramp() {
+        // 20 degrees, its low end flush with the floor around z = -1.1
+        let colliders = [floor(FLOOR_SIZE), ramp(Vec3::new(0., 0.85, -4.), Vec3::new(2., 0.2, 3.), 20.)];
+        let result = walk(&colliders, Vec3::new(0., REST_Y, 0.), Vec3::new(0., 0., -0.05), 100);
+        assert!(result.grounded);
+        assert!(result.position.y > 1., "{:?}", result.position);
+        let ramp_normal = Quat::from_rotation_x(20f32.to_radians()) * Vec3::Y;
+        assert!(result.ground_normal.abs_diff_eq(ramp_normal, 1e-3), "{:?}", result.ground_normal);
+    }
+
+    #[test]
+    fn refuses_steep_ramp() {
+        // 60 degrees, steeper than max_slope, rising out of the floor around z = -2.8
+        let settings = ControllerSettings::default();
+        assert!(60f32.to_radians() > settings.max_slope);
+        let colliders = [floor(FLOOR_SIZE), ramp(Vec3::new(0., 0., -3.), Vec3::new(2., 0.2, 2.), 60.)];
+        let result = walk(&colliders, Vec3::new(0., REST_Y, 0.), Vec3::new(0., 0., -0.05), 100);
+        assert!(result.position.y < 0.1, "{:?}", result.position);
+        assert!(result.position.z > -2.8, "{:?}", result.position);
+        assert!(result.grounded);
+        assert!(result.ground_normal.abs_diff_eq(Vec3::Y, 1e-3), "{:?}", result.ground_normal);
+    }
+
+    #[test]
+    fn snaps_down_off_ledge() {
+        // Platform 0.2 high, under the snap distance, its edge at z = 1
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(0., 0.1, 0.), Vec3::new(5., 0.1, 1.))];
+        let settings = ControllerSettings::default();
+        let start = Vec3::new(0., 0.2 + REST_Y, 0.8);
+        let motion = Vec3::new(0., -0.02, 0.7);
+
+        let result = move_character(&colliders, &CAPSULE, &settings, start, motion, true);
+        assert!(result.grounded);
+        assert!((result.position.y - REST_Y).abs() < 0.02, "{:?}", result.position);
+
+        // Only when it was on the ground, otherwise it falls
+        let result = move_character(&colliders, &CAPSULE, &settings, start, motion, false);
+        assert!(!result.grounded);
+        assert!((result.position.y - (0.2 + REST_Y - 0.02)).abs() < 1e-3, "{:?}", result.position);
+    }
+
+    #[test]
+    fn ray_cast_hits_closest() {
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(0., 0.5, 0.), Vec3::splat(0.5))];
+        let hit = ray_cast(&colliders, Vec3::new(0., 5., 0.), Vec3::NEG_Y, 10.).unwrap();
+        assert!((hit.distance - 4.).abs() < 1e-4);
+        assert!(hit.point.abs_diff_eq(Vec3::Y, 1e-4));
+        assert!(hit.normal.abs_diff_eq(Vec3::Y, 1e-4));
+        assert!(ray_cast(&colliders, Vec3::new(0., 5., 0.), Vec3::NEG_Y, 3.).is_none());
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+    }
+};
+use serde::Deserialize;
+
+// One custom GLSL material for all examples.
+// Optional effects are not separate materials and shaders, instead each feature becomes a shader define
+// in custom_material.frag, picked per pipeline through the material key in specialize().
+
+// How the material reacts to the scene's lights
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum CustomLighting {
+    Unlit,
+    Simple, // Lambert diffuse, blinn-phong specular and rim from the directional lights
+    Pbr,    // Port of Bevy's PBR lighting functions
+}
+
+// Optional shader features, each one is a shader define
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct CustomMaterialFeatures {
+    pub scrolling_texture: bool, // Scroll the color texture by scroll_speed over time
+    pub marble_noise: bool,      // Distort the color texture with the animated noise texture
+    pub lighting: CustomLighting,
+}
+impl Default for CustomMaterialFeatures
+{
+    fn default() -> Self {
+        Self {
+            scrolling_texture: false,
+            marble_noise: false,
+            lighting: CustomLighting::Simple,
+        }
+    }
+}
+
+// Shader buffer bindings
+// https://docs.rs/bevy/0.8.0/bevy/render/render_resource/trait.AsBindGroup.html
+#[derive(AsBindGroup, Clone, TypeUuid)]
+#[uuid = "69196246-07cd-4581-9885-167958593672"]
+#[bind_group_data(MyCustomMaterialKey)]
+pub struct MyCustomMaterial {
+    #[uniform(0)]
+    pub color: Color,
+    #[uniform(0)]
+    pub roughness: f32,
+    #[uniform(0)]
+    pub metallic: f32,
+    #[uniform(0)]
+    pub rim: f32,
+    #[uniform(0)]
+    pub distortion: f32,   // Strength of the marble noise distortion
+    #[uniform(0)]
+    pub scroll_speed: Vec2, // Texture coordinates per second
+    #[texture(1)]
+    #[sampler(2)]
+    pub color_texture: Option<Handle<Image>>,
+    #[texture(3)]
+    #[sampler(4)]
+    pub noise_texture: Option<Handle<Image>>,
+    pub alpha_mode: AlphaMode,
+    pub features: CustomMaterialFeatures,
+}
+impl Default for MyCustomMaterial
+{
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            roughness: 0.5,
+            metallic: 0.0,
+            rim: 0.0,
+            distortion: 0.1,
+            scroll_speed: Vec2::ZERO,
+            color_texture: None,
+            noise_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            features: CustomMaterialFeatures::default(),
+        }
+    }
+}
+
+// Material properties that need their own pipeline variant
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct MyCustomMaterialKey {
+    features: CustomMaterialFeatures,
+    alpha_blend: bool,
+}
+
+impl From<&MyCustomMaterial> for MyCustomMaterialKey {
+    fn from(material: &MyCustomMaterial) -> Self {
+        Self {
+            features: material.features,
+            alpha_blend: material.alpha_mode == AlphaMode::Blend,
+        }
+    }
+}
+
+// Implement the material trait for our custom material struct in order to make it compliant with shader pipeline.
+// Override the behaviours for which we don't want the default behaviours.
+ impl Material for MyCustomMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/custom_material.vert".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/custom_material.frag".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    // Specify shader program entrypoint overrides (not needed for WGSL)
+    // and turn the material features into shader defines.
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> 
+    {
+        descriptor.vertex.entry_point = "main".into();
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        fragment.entry_point = "main".into();
+
+        let features = key.bind_group_data.features;
+        if features.scrolling_texture {
+            fragment.shader_defs.push("SCROLLING_TEXTURE".into());
+        }
+        if features.marble_noise {
+            fragment.shader_defs.push("MARBLE_NOISE".into());
+        }
+        if key.bind_group_data.alpha_blend {
+            fragment.shader_defs.push("ALPHA_BLEND".into());
+        }
+        match features.lighting {
+            CustomLighting::Unlit => {}
+            CustomLighting::Simple => {
+                fragment.shader_defs.push("LIGHTING".into());
+            }
+            CustomLighting::Pbr => {
+                fragment.shader_defs.push("LIGHTING".into());
+                fragment.shader_defs.push("PBR_LIGHTING".into());
+            }
+        }
+        Ok(())
+    }
+ }
@@ -0,0 +1,12 @@
+// Shared code for the playground examples.
+// Each example is still its own little binary, but things used by several of them live here
+// and are reached through the bevy_playground crate, e.g. bevy_playground::custom_material.
+
+pub mod custom_material;
+pub mod material_asset;
+pub mod collision;
+pub mod navigation;
+pub mod mouse_look;
+pub mod camera_rig;
+pub mod camera_path;
+pub mod camera_shake;
@@ -0,0 +1,116 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+};
+use serde::Deserialize;
+use crate::custom_material::{MyCustomMaterial, CustomMaterialFeatures};
+
+// Loads .material.ron files describing a MyCustomMaterial, so material parameters
+// live in data instead of being hard-coded where the material is spawned.
+// Load one with asset_server.load("materials/name.material.ron") to get a Handle<MyCustomMaterial>.
+// With AssetPlugin::watch_for_changes the material is updated live when the file is edited.
+
+pub struct MaterialAssetPlugin;
+
+impl Plugin for MaterialAssetPlugin{
+    fn build(&self, app: &mut App) {
+        app.add_asset_loader(MaterialDescriptionLoader);
+    }
+}
+
+// Serializable mirror of AlphaMode, without Mask as custom_material.frag has no alpha cutoff
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum AlphaModeDescription {
+    Opaque,
+    Blend,
+}
+
+impl From<AlphaModeDescription> for AlphaMode {
+    fn from(alpha_mode: AlphaModeDescription) -> Self {
+        match alpha_mode {
+            AlphaModeDescription::Opaque => AlphaMode::Opaque,
+            AlphaModeDescription::Blend => AlphaMode::Blend,
+        }
+    }
+}
+
+// The file format, any field left out gets the MyCustomMaterial default.
+// Texture paths are relative to the assets folder.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub variant: CustomMaterialFeatures,
+    pub color: Color,
+    pub color_texture: Option<String>,
+    pub noise_texture: Option<String>,
+    pub alpha_mode: AlphaModeDescription,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub rim: f32,
+    pub distortion: f32,
+    pub scroll_speed: [f32; 2],
+}
+impl Default for MaterialDescription
+{
+    fn default() -> Self {
+        let material = MyCustomMaterial::default();
+        Self {
+            variant: material.features,
+            color: material.color,
+            color_texture: None,
+            noise_texture: None,
+            alpha_mode: AlphaModeDescription::Opaque,
+            roughness: material.roughness,
+            metallic: material.metallic,
+            rim: material.rim,
+            distortion: material.distortion,
+            scroll_speed: material.scroll_speed.to_array(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MaterialDescriptionLoader;
+
+impl AssetLoader for MaterialDescriptionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let description = ron::de::from_bytes::<MaterialDescription>(bytes)?;
+
+            // Textures become dependencies of the material, loaded through the asset server
+            let mut dependencies = Vec::new();
+            let mut load_texture = |path: &Option<String>| -> Option<Handle<Image>> {
+                path.as_ref().map(|path| {
+                    let asset_path = AssetPath::new(path.into(), None);
+                    dependencies.push(asset_path.clone());
+                    load_context.get_handle(asset_path)
+                })
+            };
+            let color_texture = load_texture(&description.color_texture);
+            let noise_texture = load_texture(&description.noise_texture);
+
+            let material = MyCustomMaterial {
+                color: description.color,
+                roughness: description.roughness,
+                metallic: description.metallic,
+                rim: description.rim,
+                distortion: description.distortion,
+                scroll_speed: Vec2::from_array(description.scroll_speed),
+                color_texture,
+                noise_texture,
+                alpha_mode: description.alpha_mode.into(),
+                features: description.variant,
+            };
+            load_context.set_default_asset(LoadedAsset::new(material).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["material.ron"]
+    }
+}
@@ -0,0 +1,130 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::CursorGrabMode,
+};
+
+// Mouse look for the example cameras. Click in the window to grab the cursor, Escape lets it go.
+// While it is grabbed, mouse motion is collected into MouseLookInput once a frame, before Update,
+// for the camera rig to add to its yaw and pitch. The wheel zooms follow cameras any time.
+// Sensitivity and invert Y are in the MouseLookSettings resource, in the inspector.
+
+pub struct MouseLookPlugin;
+
+impl Plugin for MouseLookPlugin{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MouseLookSettings>()
+        .init_resource::<MouseLookInput>()
+        .register_type::<MouseLookSettings>()
+        .register_type::<FollowDistance>()
+        .add_system_to_stage(CoreStage::PreUpdate, mouse_grab)
+        .add_system_to_stage(CoreStage::PreUpdate, mouse_look_input.after(mouse_grab));
+    }
+}
+
+// Wheel scrolling in pixels, like on touchpads, counts as lines of this many pixels
+const PIXELS_PER_LINE: f32 = 100.;
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct MouseLookSettings {
+    pub enabled: bool,
+    pub sensitivity: f32,      // Radians per pixel
+    pub invert_y: bool,
+    pub zoom_sensitivity: f32, // Fraction of the distance per wheel line
+    pub grab_button: MouseButton,
+}
+impl Default for MouseLookSettings
+{
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sensitivity: 0.003,
+            invert_y: false,
+            zoom_sensitivity: 0.1,
+            grab_button: MouseButton::Left,
+        }
+    }
+}
+
+// This frame's mouse look
+#[derive(Resource, Default)]
+pub struct MouseLookInput {
+    pub rotation: Vec2, // To add to a camera's rotation, x pitch (up is positive) and y yaw, radians
+    pub zoom: f32,      // Wheel lines, positive zooms in
+    pub grabbed: bool,
+}
+
+// Distance of a camera rig mode from its target, zoomed with the wheel
+#[derive(Reflect)]
+pub struct FollowDistance {
+    pub distance: f32,
+    pub min: f32,
+    pub max: f32,
+}
+impl Default for FollowDistance
+{
+    fn default() -> Self {
+        Self {
+            distance: 5.,
+            min: 1.5,
+            max: 20.,
+        }
+    }
+}
+
+impl FollowDistance {
+    pub fn zoom(&mut self, lines: f32, sensitivity: f32) {
+        self.distance = (self.distance * (1. - sensitivity).powf(lines)).clamp(self.min, self.max);
+    }
+}
+
+// Confined where the platform can't lock the cursor in place
+fn grab_mode() -> CursorGrabMode {
+    if cfg!(target_os = "windows") {CursorGrabMode::Confined} else {CursorGrabMode::Locked}
+}
+
+fn mouse_grab(
+    settings: Res<MouseLookSettings>,
+    mouse_input: Res<Input<MouseButton>>,
+    kb_input: Res<Input<KeyCode>>,
+    mut windows: ResMut<Windows>,
+    mut look_input: ResMut<MouseLookInput>,
+){
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    let grab = settings.enabled && !look_input.grabbed && mouse_input.just_pressed(settings.grab_button);
+    let release = look_input.grabbed && (kb_input.just_pressed(KeyCode::Escape) || !settings.enabled);
+    if grab {
+        window.set_cursor_grab_mode(grab_mode());
+        window.set_cursor_visibility(false);
+        look_input.grabbed = true;
+    } else if release {
+        window.set_cursor_grab_mode(CursorGrabMode::None);
+        window.set_cursor_visibility(true);
+        look_input.grabbed = false;
+    }
+}
+
+fn mouse_look_input(
+    settings: Res<MouseLookSettings>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut look_input: ResMut<MouseLookInput>,
+){
+    let motion = motion_events.iter().fold(Vec2::ZERO, |motion, event| motion + event.delta);
+    let zoom: f32 = wheel_events.iter().map(|event| match event.unit {
+        MouseScrollUnit::Line => event.y,
+        MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+    }).sum();
+    look_input.zoom = if settings.enabled {zoom} else {0.};
+    if !look_input.grabbed {
+        look_input.rotation = Vec2::ZERO;
+        return;
+    }
+    // Screen Y is down, moving the mouse up looks up
+    let invert_y = if settings.invert_y {-1.} else {1.};
+    look_input.rotation = Vec2::new(-motion.y * invert_y, -motion.x) * settings.sensitivity;
+}
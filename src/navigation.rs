@@ -0,0 +1,459 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+use bevy::{prelude::*, utils::HashMap};
+use crate::collision::{Capsule, Collider, ray_cast};
+
+// Navigation mesh and path finding on static geometry.
+// Plain functions on a list of colliders, no ECS, like collision.rs.
+//
+// The mesh is built from a grid over the level: each cell takes the height of the topmost surface
+// above it, and is walkable if that surface isn't too steep and the agent's capsule fits there.
+// Walkable cells of the same height are merged into rectangles, the polygons of the mesh, and
+// neighbouring polygons are linked where the agent can step from one to the other.
+// Paths are found with A* over the polygons and pulled tight through the portals between them
+// with the funnel algorithm. One layer only, the ground under an overhang isn't walkable.
+
+// Cells this much apart in height still make up one flat polygon
+const HEIGHT_TOLERANCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug)]
+pub struct NavMeshSettings {
+    pub cell_size: f32,
+    pub agent: Capsule,
+    pub max_slope: f32,   // Radians, like ControllerSettings
+    pub step_height: f32,
+}
+
+// A way from one polygon into another, through the portal between them
+#[derive(Clone, Copy, Debug)]
+pub struct NavLink {
+    pub polygon: usize,
+    pub portal: (Vec3, Vec3),
+}
+
+// A flat rectangle, min and max on the ground plane as (x, z)
+#[derive(Clone, Debug)]
+pub struct NavPolygon {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub height: f32,
+    pub links: Vec<NavLink>,
+}
+
+impl NavPolygon {
+    pub fn center(&self) -> Vec3 {
+        let center = (self.min + self.max) * 0.5;
+        Vec3::new(center.x, self.height, center.y)
+    }
+
+    // Closest point of the polygon to a point, on the ground plane
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        let clamped = Vec2::new(point.x, point.z).clamp(self.min, self.max);
+        Vec3::new(clamped.x, self.height, clamped.y)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NavMesh {
+    pub polygons: Vec<NavPolygon>,
+}
+
+// Open list entry for A*, ordered so the BinaryHeap pops the lowest estimate first
+#[derive(PartialEq)]
+struct OpenNode {
+    estimate: f32,
+    polygon: usize,
+}
+impl Eq for OpenNode {}
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+    }
+}
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn flat(v: Vec3) -> Vec2 {
+    Vec2::new(v.x, v.z)
+}
+
+// Does the capsule standing at position overlap any of the colliders
+fn capsule_blocked(colliders: &[Collider], capsule: &Capsule, position: Vec3) -> bool {
+    let (bottom, top) = capsule.segment(position);
+    let center = (bottom + top) * 0.5;
+    let reach = capsule.height * 0.5;
+    colliders.iter().any(|collider| {
+        let (sphere_center, sphere_radius) = collider.bounding_sphere();
+        (sphere_center - center).length() < sphere_radius + reach
+            && collider.segment_contact(bottom, top).distance < capsule.radius
+    })
+}
+
+// Simple stupid funnel algorithm (Mononen). portals are (left, right) seen in the direction of
+// travel, the first and last are the start and end point twice. Only x and z count for the
+// funnel, the corners keep their heights.
+pub fn string_pull(portals: &[(Vec3, Vec3)]) -> Vec<Vec3> {
+    let mut path = Vec::new();
+    if portals.is_empty() {
+        return path;
+    }
+    let mut apex = portals[0].0;
+    let mut left = portals[0].0;
+    let mut right = portals[0].1;
+    let (mut apex_index, mut left_index, mut right_index) = (0, 0, 0);
+    path.push(apex);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (portal_left, portal_right) = portals[i];
+        let apex_2d = flat(apex);
+
+        // Tighten the right side of the funnel, unless it crosses over the left
+        if cross(flat(right) - apex_2d, flat(portal_right) - apex_2d) >= 0. {
+            if flat(apex) == flat(right) || cross(flat(left) - apex_2d, flat(portal_right) - apex_2d) < 0. {
+                right = portal_right;
+                right_index = i;
+            } else {
+                // The left corner is on the path, restart the funnel from there
+                path.push(left);
+                apex = left;
+                apex_index = left_index;
+                right = apex;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        // The same for the left side
+        if cross(flat(left) - apex_2d, flat(portal_left) - apex_2d) <= 0. {
+            if flat(apex) == flat(left) || cross(flat(right) - apex_2d, flat(portal_left) - apex_2d) > 0. {
+                left = portal_left;
+                left_index = i;
+            } else {
+                path.push(right);
+                apex = right;
+                apex_index = right_index;
+                left = apex;
+                left_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    let end = portals[portals.len() - 1].0;
+    if path.last().map_or(true, |last| *last != end) {
+        path.push(end);
+    }
+    path
+}
+
+impl NavMesh {
+    pub fn build(colliders: &[Collider], settings: &NavMeshSettings) -> Self {
+        if colliders.is_empty() || settings.cell_size <= 0. {
+            return Self::default();
+        }
+        let (mut min, mut max) = colliders[0].bounds();
+        for collider in colliders.iter().skip(1) {
+            let (collider_min, collider_max) = collider.bounds();
+            min = min.min(collider_min);
+            max = max.max(collider_max);
+        }
+        let cell_size = settings.cell_size;
+        let columns = ((max.x - min.x) / cell_size).ceil() as usize;
+        let rows = ((max.z - min.z) / cell_size).ceil() as usize;
+        let min_ground_y = settings.max_slope.cos();
+
+        // Height of the walkable surface of each cell, None if it isn't walkable
+        let ray_top = max.y + 1.;
+        let ray_length = ray_top - min.y + 1.;
+        let mut heights: Vec<Option<f32>> = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let origin = Vec3::new(
+                    min.x + (column as f32 + 0.5) * cell_size,
+                    ray_top,
+                    min.z + (row as f32 + 0.5) * cell_size);
+                // Lifted by the step height, so the ground itself and what can be stepped over don't block
+                let height = ray_cast(colliders, origin, Vec3::NEG_Y, ray_length)
+                    .filter(|hit| hit.normal.y >= min_ground_y)
+                    .map(|hit| hit.point.y)
+                    .filter(|height| !capsule_blocked(colliders, &settings.agent, Vec3::new(origin.x, height + settings.step_height, origin.z)));
+                heights.push(height);
+            }
+        }
+
+        // Merge cells into rectangles, greedily, first along the row then down the rows
+        let mut cell_polygons = vec![usize::MAX; columns * rows];
+        let mut polygons = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let height = match heights[row * columns + column] {
+                    Some(height) if cell_polygons[row * columns + column] == usize::MAX => height,
+                    _ => continue,
+                };
+                let free = |column: usize, row: usize| column < columns && row < rows
+                    && cell_polygons[row * columns + column] == usize::MAX
+                    && heights[row * columns + column].map_or(false, |other| (other - height).abs() <= HEIGHT_TOLERANCE);
+                let mut width = 1;
+                while free(column + width, row) {
+                    width += 1;
+                }
+                let mut depth = 1;
+                while (0..width).all(|offset| free(column + offset, row + depth)) {
+                    depth += 1;
+                }
+                for cell_row in row..row + depth {
+                    for cell_column in column..column + width {
+                        cell_polygons[cell_row * columns + cell_column] = polygons.len();
+                    }
+                }
+                polygons.push(NavPolygon {
+                    min: Vec2::new(min.x + column as f32 * cell_size, min.z + row as f32 * cell_size),
+                    max: Vec2::new(min.x + (column + width) as f32 * cell_size, min.z + (row + depth) as f32 * cell_size),
+                    height,
+                    links: Vec::new(),
+                });
+            }
+        }
+
+        // Link neighbouring polygons where the agent can step across,
+        // the portal is the part of their shared edge that it can step across on
+        let mut portals: HashMap<(usize, usize), (Vec2, Vec2, f32)> = HashMap::default();
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = row * columns + column;
+                let height = match heights[index] {
+                    Some(height) => height,
+                    None => continue,
+                };
+                for (next_column, next_row) in [(column + 1, row), (column, row + 1)] {
+                    if next_column >= columns || next_row >= rows {
+                        continue;
+                    }
+                    let next_index = next_row * columns + next_column;
+                    let (polygon, next_polygon) = (cell_polygons[index], cell_polygons[next_index]);
+                    let next_height = match heights[next_index] {
+                        Some(next_height) if polygon != next_polygon => next_height,
+                        _ => continue,
+                    };
+                    if (next_height - height).abs() > settings.step_height {
+                        continue;
+                    }
+                    // The edge between the two cells
+                    let corner = Vec2::new(min.x + next_column as f32 * cell_size, min.z + next_row as f32 * cell_size);
+                    let edge = if next_column > column {Vec2::new(0., cell_size)} else {Vec2::new(cell_size, 0.)};
+                    let key = (polygon.min(next_polygon), polygon.max(next_polygon));
+                    let portal = portals.entry(key).or_insert((corner, corner + edge, f32::MIN));
+                    portal.0 = portal.0.min(corner);
+                    portal.1 = portal.1.max(corner + edge);
+                    portal.2 = portal.2.max(height.max(next_height));
+                }
+            }
+        }
+        for ((a, b), (start, end, height)) in portals {
+            let portal = (Vec3::new(start.x, height, start.y), Vec3::new(end.x, height, end.y));
+            polygons[a].links.push(NavLink {polygon: b, portal});
+            polygons[b].links.push(NavLink {polygon: a, portal});
+        }
+
+        Self {polygons}
+    }
+
+    // Polygon nearest to a point, preferring the ones it is over at about the right height
+    pub fn find_polygon(&self, point: Vec3) -> Option<usize> {
+        let score = |polygon: &NavPolygon| {
+            let closest = polygon.closest_point(point);
+            (flat(closest) - flat(point)).length() * 10. + (polygon.height - point.y).abs()
+        };
+        self.polygons.iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| score(a).partial_cmp(&score(b)).unwrap_or(Ordering::Equal))
+            .map(|(index, _)| index)
+    }
+
+    // Polygons from the start polygon to the goal polygon, by A* between polygon centers
+    pub fn find_corridor(&self, start: usize, goal: usize) -> Option<Vec<usize>> {
+        let mut open = BinaryHeap::new();
+        let mut cost = vec![f32::MAX; self.polygons.len()];
+        let mut came_from = vec![usize::MAX; self.polygons.len()];
+        let goal_center = self.polygons[goal].center();
+        cost[start] = 0.;
+        open.push(OpenNode {estimate: (self.polygons[start].center() - goal_center).length(), polygon: start});
+
+        while let Some(OpenNode {polygon, ..}) = open.pop() {
+            if polygon == goal {
+                let mut corridor = vec![goal];
+                let mut current = goal;
+                while current != start {
+                    current = came_from[current];
+                    corridor.push(current);
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+            let center = self.polygons[polygon].center();
+            for link in self.polygons[polygon].links.iter() {
+                let next_center = self.polygons[link.polygon].center();
+                let next_cost = cost[polygon] + (next_center - center).length();
+                if next_cost < cost[link.polygon] {
+                    cost[link.polygon] = next_cost;
+                    came_from[link.polygon] = polygon;
+                    open.push(OpenNode {estimate: next_cost + (next_center - goal_center).length(), polygon: link.polygon});
+                }
+            }
+        }
+        None
+    }
+
+    // Shortest path from start to goal, both moved onto the mesh. None if there's no way there.
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start_polygon = self.find_polygon(start)?;
+        let goal_polygon = self.find_polygon(goal)?;
+        let corridor = self.find_corridor(start_polygon, goal_polygon)?;
+        let start = self.polygons[start_polygon].closest_point(start);
+        let goal = self.polygons[goal_polygon].closest_point(goal);
+
+        // Portals along the corridor, sorted into left and right as seen going through
+        let mut portals = vec![(start, start)];
+        for pair in corridor.windows(2) {
+            let (from, to) = (&self.polygons[pair[0]], &self.polygons[pair[1]]);
+            let link = from.links.iter().find(|link| link.polygon == pair[1])?;
+            let (a, b) = link.portal;
+            let direction = flat(to.center() - from.center());
+            let middle = flat((a + b) * 0.5);
+            portals.push(if cross(direction, flat(a) - middle) > 0. {(a, b)} else {(b, a)});
+        }
+        portals.push((goal, goal));
+        Some(string_pull(&portals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::test_scenes::{cuboid, floor};
+
+    fn settings() -> NavMeshSettings {
+        NavMeshSettings {
+            cell_size: 0.5,
+            agent: Capsule {radius: 0.3, height: 1.8},
+            max_slope: 0.8,
+            step_height: 0.25,
+        }
+    }
+
+    // 10 by 10
+    const FLOOR_SIZE: f32 = 5.;
+
+    fn assert_path(path: &[Vec3], expected: &[Vec3]) {
+        assert_eq!(path.len(), expected.len(), "{:?}", path);
+        for (point, expected) in path.iter().zip(expected.iter()) {
+            assert!(point.abs_diff_eq(*expected, 1e-4), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn straight_path_on_open_floor() {
+        let navmesh = NavMesh::build(&[floor(FLOOR_SIZE)], &settings());
+        assert_eq!(navmesh.polygons.len(), 1);
+        let (start, goal) = (Vec3::new(-3., 0., -3.), Vec3::new(3., 0., 4.));
+        assert_path(&navmesh.find_path(start, goal).unwrap(), &[start, goal]);
+    }
+
+    #[test]
+    fn path_bends_around_wall_corner() {
+        // Wall from the -x edge to x = 2, the way around is past its end.
+        // The corners keep the agent's radius to the wall, rounded out to the cells.
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(-1.5, 1., 0.), Vec3::new(3.5, 1., 0.3))];
+        let navmesh = NavMesh::build(&colliders, &settings());
+        let (start, goal) = (Vec3::new(0., 0., 3.), Vec3::new(0., 0., -3.));
+        assert_path(&navmesh.find_path(start, goal).unwrap(),
+            &[start, Vec3::new(2.5, 0., 0.5), Vec3::new(2.5, 0., -0.5), goal]);
+    }
+
+    #[test]
+    fn no_path_into_enclosure() {
+        let colliders = [
+            floor(FLOOR_SIZE),
+            cuboid(Vec3::new(2.5, 1., 0.75), Vec3::new(2., 1., 0.25)),
+            cuboid(Vec3::new(2.5, 1., 4.25), Vec3::new(2., 1., 0.25)),
+            cuboid(Vec3::new(0.75, 1., 2.5), Vec3::new(0.25, 1., 2.)),
+            cuboid(Vec3::new(4.25, 1., 2.5), Vec3::new(0.25, 1., 2.)),
+        ];
+        let navmesh = NavMesh::build(&colliders, &settings());
+        let inside = Vec3::new(2.5, 0., 2.5);
+        let inside_polygon = navmesh.find_polygon(inside).unwrap();
+        assert!(navmesh.polygons[inside_polygon].min.x < 2.5 && navmesh.polygons[inside_polygon].max.x > 2.5);
+        assert!(navmesh.find_path(Vec3::new(-3., 0., -3.), inside).is_none());
+    }
+
+    #[test]
+    fn low_step_is_linked() {
+        // 0.2 high from x = 0, under the step height
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(2.5, 0.1, 0.), Vec3::new(2.5, 0.1, 5.))];
+        let navmesh = NavMesh::build(&colliders, &settings());
+        assert_eq!(navmesh.polygons.len(), 2);
+        for (index, polygon) in navmesh.polygons.iter().enumerate() {
+            assert_eq!(polygon.links.len(), 1);
+            assert_eq!(polygon.links[0].polygon, 1 - index);
+        }
+        let (start, goal) = (Vec3::new(-3., 0., 0.), Vec3::new(3., 0.2, 1.));
+        assert_path(&navmesh.find_path(start, goal).unwrap(), &[start, goal]);
+    }
+
+    #[test]
+    fn high_step_is_not_linked() {
+        // 0.5 high from x = 0, over the step height
+        let colliders = [floor(FLOOR_SIZE), cuboid(Vec3::new(2.5, 0.25, 0.), Vec3::new(2.5, 0.25, 5.))];
+        let navmesh = NavMesh::build(&colliders, &settings());
+        assert!(navmesh.polygons.iter().any(|polygon| (polygon.height - 0.5).abs() < 1e-4));
+        assert!(navmesh.polygons.iter().all(|polygon| polygon.links.is_empty()));
+        assert!(navmesh.find_path(Vec3::new(-3., 0., 0.), Vec3::new(3., 0.5, 1.)).is_none());
+    }
+
+    // Going -z, left is +x. The path has to go around the near corner of the first portal.
+    #[test]
+    fn string_pull_bends_left() {
+        let (start, end) = (Vec3::ZERO, Vec3::new(6., 0., -6.));
+        let path = string_pull(&[
+            (start, start),
+            (Vec3::new(1., 0., -2.), Vec3::new(-1., 0., -2.)),
+            (Vec3::new(4.5, 0., -4.), Vec3::new(2.5, 0., -4.)),
+            (end, end),
+        ]);
+        assert_path(&path, &[start, Vec3::new(1., 0., -2.), end]);
+    }
+
+    #[test]
+    fn string_pull_bends_right() {
+        let (start, end) = (Vec3::ZERO, Vec3::new(-6., 0., -6.));
+        let path = string_pull(&[
+            (start, start),
+            (Vec3::new(1., 0., -2.), Vec3::new(-1., 0., -2.)),
+            (Vec3::new(-2.5, 0., -4.), Vec3::new(-4.5, 0., -4.)),
+            (end, end),
+        ]);
+        assert_path(&path, &[start, Vec3::new(-1., 0., -2.), end]);
+    }
+
+    #[test]
+    fn string_pull_straight_through_wide_portals() {
+        let (start, end) = (Vec3::ZERO, Vec3::new(0.5, 0., -6.));
+        let path = string_pull(&[
+            (start, start),
+            (Vec3::new(2., 0., -2.), Vec3::new(-2., 0., -2.)),
+            (Vec3::new(2., 0., -4.), Vec3::new(-2., 0., -4.)),
+            (end, end),
+        ]);
+        assert_path(&path, &[start, end]);
+    }
+}